@@ -0,0 +1,224 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyinput::anyinput;
+
+use crate::{
+    FetchData, FetchDataSpecificError, FetchOptions, FileLocks, Internals, MismatchPolicy,
+    RetryPolicy,
+};
+
+/// Builds a [`FetchData`] from named settings. Create one with [`FetchData::builder`].
+///
+/// # Example
+/// ```
+/// use fetch_data::FetchData;
+/// use std::time::Duration;
+///
+/// let fetch_data = FetchData::builder()
+///     .registry_contents(
+///         "small.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2",
+///     )
+///     .url_root("https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/")
+///     .env_key("BAR_APP_DATA_DIR")
+///     .qualifier("com")
+///     .organization("Foo Corp")
+///     .application("Bar App")
+///     .timeout(Duration::from_secs(60))
+///     .build();
+///
+/// let local_path = fetch_data.fetch_file("small.fam")?;
+/// assert!(local_path.exists());
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FetchDataBuilder {
+    registry_contents: String,
+    url_root_list: Vec<String>,
+    env_key: String,
+    qualifier: String,
+    organization: String,
+    application: String,
+    cache_dir: Option<PathBuf>,
+    options: FetchOptions,
+}
+
+impl FetchDataBuilder {
+    /// Whitespace delimited list of files and hashes. A hash may start with a Pooch-style
+    /// algorithm prefix, for example, `md5:`. See [`HashAlgorithm`](crate::HashAlgorithm).
+    /// An optional third column gives a file's full URL, used instead of the URL roots.
+    /// The file is still cached under the name in the first column. Blank lines and `#` comments are ignored.
+    /// Use Rust's [`std::include_str`](https://doc.rust-lang.org/std/macro.include_str.html)
+    /// macro to include the contents of a file.
+    #[anyinput]
+    #[must_use]
+    pub fn registry_contents(mut self, registry_contents: AnyString) -> Self {
+        registry_contents.clone_into(&mut self.registry_contents);
+        self
+    }
+
+    /// Base URL for remote files.
+    #[anyinput]
+    #[must_use]
+    pub fn url_root(mut self, url_root: AnyString) -> Self {
+        self.url_root_list = vec![url_root.to_string()];
+        self
+    }
+
+    /// Base URLs for remote files, in the order to try them. If a file can't be downloaded
+    /// from one, the next is tried. If none work, the error tells why each failed.
+    ///
+    /// # Example
+    /// ```
+    /// use fetch_data::FetchData;
+    ///
+    /// let fetch_data = FetchData::builder()
+    ///     .registry_contents(
+    ///         "small.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2",
+    ///     )
+    ///     .url_roots([
+    ///         "https://mirror.invalid/fetch-data/",
+    ///         "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/",
+    ///     ])
+    ///     .env_key("BAR_APP_DATA_DIR")
+    ///     .qualifier("com")
+    ///     .organization("Foo Corp")
+    ///     .application("Bar App")
+    ///     .build();
+    ///
+    /// let local_path = fetch_data.fetch_file("small.fam")?;
+    /// assert!(local_path.exists());
+    /// # use fetch_data::FetchDataError;
+    /// # Ok::<(), Box<FetchDataError>>(())
+    /// ```
+    #[anyinput]
+    #[must_use]
+    pub fn url_roots(mut self, url_roots: AnyIter<AnyString>) -> Self {
+        self.url_root_list = url_roots
+            .map(|url_root| url_root.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Environment variable that may contain the path to the data directory.
+    #[anyinput]
+    #[must_use]
+    pub fn env_key(mut self, env_key: AnyString) -> Self {
+        env_key.clone_into(&mut self.env_key);
+        self
+    }
+
+    /// The reverse domain name notation of the application, excluding the organization or application name itself.
+    /// Used with [`organization`](Self::organization) and [`application`](Self::application) to create the data
+    /// directory via [`ProjectDirs`](https://docs.rs/directories/latest/directories/struct.ProjectDirs.html#method.from_path)
+    /// when neither [`cache_dir`](Self::cache_dir) nor the [`env_key`](Self::env_key) variable gives it.
+    #[anyinput]
+    #[must_use]
+    pub fn qualifier(mut self, qualifier: AnyString) -> Self {
+        qualifier.clone_into(&mut self.qualifier);
+        self
+    }
+
+    /// The name of the organization that develops this application.
+    #[anyinput]
+    #[must_use]
+    pub fn organization(mut self, organization: AnyString) -> Self {
+        organization.clone_into(&mut self.organization);
+        self
+    }
+
+    /// The name of the application itself.
+    #[anyinput]
+    #[must_use]
+    pub fn application(mut self, application: AnyString) -> Self {
+        application.clone_into(&mut self.application);
+        self
+    }
+
+    /// The data directory. If given, the [`env_key`](Self::env_key) variable and
+    /// [`ProjectDirs`](https://docs.rs/directories/latest/directories/struct.ProjectDirs.html#method.from_path) are not used.
+    #[anyinput]
+    #[must_use]
+    pub fn cache_dir(mut self, cache_dir: AnyPath) -> Self {
+        self.cache_dir = Some(cache_dir.to_path_buf());
+        self
+    }
+
+    /// Set the timeout for each HTTP request. See [`FetchOptions::timeout`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options = self.options.timeout(timeout);
+        self
+    }
+
+    /// Set the proxy for HTTP requests. See [`FetchOptions::proxy`].
+    #[anyinput]
+    #[must_use]
+    pub fn proxy(mut self, proxy: AnyString) -> Self {
+        self.options = self.options.proxy(proxy);
+        self
+    }
+
+    /// Set how downloads retry transient HTTP failures. See [`FetchOptions::retry_policy`].
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options = self.options.retry_policy(retry_policy);
+        self
+    }
+
+    /// Set what to do when a local file has the wrong hash. See [`FetchOptions::mismatch_policy`].
+    #[must_use]
+    pub fn mismatch_policy(mut self, mismatch_policy: MismatchPolicy) -> Self {
+        self.options = self.options.mismatch_policy(mismatch_policy);
+        self
+    }
+
+    /// Set whether to use only files already in the cache. See [`FetchOptions::offline`].
+    #[must_use]
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.options = self.options.offline(offline);
+        self
+    }
+
+    /// Set how many files may download at once. See [`FetchOptions::max_concurrent_downloads`].
+    #[must_use]
+    pub fn max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
+        self.options = self
+            .options
+            .max_concurrent_downloads(max_concurrent_downloads);
+        self
+    }
+
+    /// Set how long to wait for another process downloading the same file. See [`FetchOptions::lock_timeout`].
+    #[must_use]
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.options = self.options.lock_timeout(lock_timeout);
+        self
+    }
+
+    /// Create the [`FetchData`].
+    ///
+    /// # Errors
+    ///
+    /// To make `FetchData` work well as a static global, `build` never fails. Instead, `FetchData` stores any error
+    /// and returns it when the first call to `fetch_file`, etc., is made.
+    #[must_use]
+    pub fn build(self) -> FetchData {
+        // Keep only the message of any error, so that `FetchData` is `Sync`.
+        let internals = Internals::new(
+            &self.registry_contents,
+            &self.url_root_list,
+            self.cache_dir.as_deref(),
+            &self.env_key,
+            &self.qualifier,
+            &self.organization,
+            &self.application,
+        )
+        .map_err(|e| FetchDataSpecificError::FetchDataNewFailed(e.to_string()));
+        FetchData {
+            internals,
+            file_locks: FileLocks::default(),
+            options: self.options,
+        }
+    }
+}
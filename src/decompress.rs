@@ -0,0 +1,158 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{processor, FetchDataError, FetchDataSpecificError, Processor};
+
+/// The compression formats that [`Decompress`] can undo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressionFormat {
+    /// gzip, `.gz`. Files of several gzip members, such as from `bgzip`, are read in full.
+    Gzip,
+    /// bzip2, `.bz2`.
+    Bzip2,
+    /// xz, `.xz`.
+    Xz,
+    /// zstd, `.zst`.
+    Zstd,
+}
+
+impl CompressionFormat {
+    /// The format of a compressed file, from its file name.
+    #[must_use]
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_ascii_lowercase();
+        [Self::Gzip, Self::Bzip2, Self::Xz, Self::Zstd]
+            .into_iter()
+            .find(|format| file_name.ends_with(format.suffix()))
+    }
+
+    /// The file name's suffix for this format, for example, `.gz`.
+    #[must_use]
+    pub const fn suffix(self) -> &'static str {
+        match self {
+            Self::Gzip => ".gz",
+            Self::Bzip2 => ".bz2",
+            Self::Xz => ".xz",
+            Self::Zstd => ".zst",
+        }
+    }
+}
+
+/// How [`FetchData::fetch_decompressed`](crate::FetchData::fetch_decompressed) decompresses a file.
+///
+/// The file is decompressed, once, into a sibling directory named `<compressed name>.decompressed`,
+/// under its name without the compression suffix. For example, `small.bed.gz` becomes
+/// `small.bed.gz.decompressed/small.bed`. (If the name has no such suffix, it is kept.) The
+/// directory keeps the output apart from the files and partial downloads of registry entries; a
+/// registry can't list paths ending with `.decompressed`.
+/// It is decompressed again only if the compressed file's registry hash changes.
+///
+/// # Example
+/// ```
+/// use fetch_data::{CompressionFormat, Decompress};
+///
+/// // Give the format when the file name doesn't end with `.gz`, `.bz2`, `.xz`, or `.zst`.
+/// let decompress = Decompress::new().format(CompressionFormat::Gzip);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Decompress {
+    format: Option<CompressionFormat>,
+}
+
+impl Decompress {
+    /// Decompress based on the file name.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the compression format, rather than going by the file name.
+    #[must_use]
+    pub const fn format(mut self, format: CompressionFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    fn output_dir(compressed: &Path) -> PathBuf {
+        let mut file_name = compressed.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".decompressed");
+        compressed.with_file_name(file_name)
+    }
+
+    fn output_name(&self, compressed: &Path) -> Result<String, Box<FetchDataError>> {
+        let format = self.format_of(compressed)?;
+        let file_name = compressed.file_name().unwrap_or_default().to_string_lossy();
+        // ASCII lowercasing keeps byte offsets, so the stem's length works on the original name.
+        let output_name = match file_name.to_ascii_lowercase().strip_suffix(format.suffix()) {
+            Some(stem) if !stem.is_empty() => file_name[..stem.len()].to_string(),
+            _ => file_name.to_string(),
+        };
+        Ok(output_name)
+    }
+
+    fn decompress(&self, compressed: &Path, output: &Path) -> Result<(), Box<FetchDataError>> {
+        let problem = |reason: String| -> Box<FetchDataError> {
+            Box::new(
+                FetchDataSpecificError::DecompressionProblem(
+                    compressed.display().to_string(),
+                    reason,
+                )
+                .into(),
+            )
+        };
+        let mut reader = BufReader::new(File::open(compressed).map_err(|e| Box::new(e.into()))?);
+        let mut writer = BufWriter::new(File::create(output).map_err(|e| Box::new(e.into()))?);
+        match self.format_of(compressed)? {
+            CompressionFormat::Gzip => copy(flate2::read::MultiGzDecoder::new(reader), &mut writer),
+            CompressionFormat::Bzip2 => copy(bzip2::read::MultiBzDecoder::new(reader), &mut writer),
+            CompressionFormat::Xz => lzma_rs::xz_decompress(&mut reader, &mut writer)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+            CompressionFormat::Zstd => ruzstd::decoding::StreamingDecoder::new(reader)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+                .and_then(|decoder| copy(decoder, &mut writer)),
+        }
+        .and_then(|()| writer.flush())
+        .map_err(|e| problem(e.to_string()))
+    }
+
+    fn format_of(&self, compressed: &Path) -> Result<CompressionFormat, Box<FetchDataError>> {
+        let file_name = compressed.file_name().unwrap_or_default().to_string_lossy();
+        self.format
+            .or_else(|| CompressionFormat::from_file_name(&file_name))
+            .ok_or_else(|| {
+                Box::new(
+                    FetchDataSpecificError::UnknownCompressionFormat(
+                        compressed.display().to_string(),
+                    )
+                    .into(),
+                )
+            })
+    }
+}
+
+impl Processor for Decompress {
+    fn id(&self) -> String {
+        format!("decompress {:?}", self.format)
+    }
+
+    fn process(
+        &self,
+        input: &Path,
+        _cache_dir: &Path,
+    ) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+        let output_name = self.output_name(input)?;
+        let output_dir = Self::output_dir(input);
+        processor::replace_with(&output_dir, |temp_dir| {
+            fs::create_dir_all(temp_dir).map_err(|e| Box::new(e.into()))?;
+            self.decompress(input, &temp_dir.join(&output_name))
+        })?;
+        Ok(vec![output_dir.join(output_name)])
+    }
+}
+
+fn copy(mut reader: impl Read, writer: &mut impl Write) -> Result<(), io::Error> {
+    io::copy(&mut reader, writer).map(|_| ())
+}
@@ -0,0 +1,169 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs::read_dir,
+    path::Path,
+};
+
+use anyinput::anyinput;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::{FetchDataError, FetchDataSpecificError};
+
+/// Which files [`dir_to_file_list_with_options`] lists.
+///
+/// Patterns are globs, matched against each file's relative path with `/` separators. `*` doesn't
+/// match `/`, but `**` does. A pattern without a `/`, such as `*.tmp` or `.DS_Store`, matches a file
+/// (or directory) of that name at any depth. An excluded directory is skipped entirely.
+///
+/// # Example
+/// ```
+/// use fetch_data::{dir_to_file_list_with_options, FileListOptions};
+///
+/// let options = FileListOptions::new()
+///     .include("*.bed")
+///     .include("*.fam")
+///     .exclude("small*");
+/// let file_list = dir_to_file_list_with_options("tests/data", &options)?;
+/// assert!(file_list.contains(&"plink_sim_10s_100v_10pmiss.bed".into()));
+/// assert!(!file_list.contains(&"small.bed".into()));
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileListOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl FileListOptions {
+    /// Create options that list every file.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List only files that match this pattern, or any other `include` pattern.
+    /// With no `include` patterns, every file is listed.
+    #[anyinput]
+    #[must_use]
+    pub fn include(mut self, pattern: AnyString) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Skip files that match this pattern, even if they match an `include` pattern.
+    #[anyinput]
+    #[must_use]
+    pub fn exclude(mut self, pattern: AnyString) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+}
+
+/// List all the files in a local directory and its subdirectories.
+///
+/// Each file is given by its path relative to the directory, with `/` separators on every
+/// platform, as used in a registry. The list is sorted.
+///
+/// # Example
+/// ```
+/// use fetch_data::{dir_to_file_list_recursive, download};
+/// use temp_testdir::TempDir;
+///
+/// // Create a local directory and download two files to it, one in a subdirectory.
+/// let temp_dir = TempDir::default();
+/// download(
+///     "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/small.fam",
+///     temp_dir.join("small.fam"),
+/// )?;
+/// download(
+///     "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/small.bim",
+///     temp_dir.join("chr1/small.bim"),
+/// )?;
+/// // List the files in the directory.
+/// let file_list = dir_to_file_list_recursive(temp_dir)?;
+/// println!("{file_list:?}"); // Prints ["chr1/small.bim", "small.fam"]
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[anyinput]
+pub fn dir_to_file_list_recursive(path: AnyPath) -> Result<Vec<OsString>, Box<FetchDataError>> {
+    dir_to_file_list_with_options(path, &FileListOptions::default())
+}
+
+/// Like [`dir_to_file_list_recursive`], but lists only the files selected by [`FileListOptions`].
+#[anyinput]
+pub fn dir_to_file_list_with_options(
+    path: AnyPath,
+    options: &FileListOptions,
+) -> Result<Vec<OsString>, Box<FetchDataError>> {
+    let include = (!options.include.is_empty())
+        .then(|| glob_set(&options.include))
+        .transpose()?;
+    let exclude = glob_set(&options.exclude)?;
+    let mut file_list = Vec::new();
+    let mut add = |name: &OsStr, is_dir: bool| {
+        let name_as_string = name.to_string_lossy();
+        if exclude.is_match(name_as_string.as_ref()) {
+            return false;
+        }
+        if !is_dir
+            && include
+                .as_ref()
+                .is_none_or(|include| include.is_match(name_as_string.as_ref()))
+        {
+            file_list.push(name.to_os_string());
+        }
+        true
+    };
+    add_files(path, OsStr::new(""), &mut add).map_err(|e| Box::new(e.into()))?;
+    file_list.sort();
+    Ok(file_list)
+}
+
+/// A glob set that also matches a pattern without a `/` at any depth.
+pub fn glob_set(pattern_list: &[String]) -> Result<GlobSet, Box<FetchDataError>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in pattern_list {
+        let full_pattern = if pattern.contains('/') {
+            pattern.clone()
+        } else {
+            format!("**/{pattern}")
+        };
+        let glob = GlobBuilder::new(&full_pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| {
+                Box::new(
+                    FetchDataSpecificError::BadGlob(pattern.clone(), e.kind().to_string()).into(),
+                )
+            })?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| {
+        Box::new(FetchDataSpecificError::BadGlob(pattern_list.join(" "), e.to_string()).into())
+    })
+}
+
+// Call `add` on each relative path under `dir`, starting with `prefix`. Skip directories
+// for which `add` returns false.
+fn add_files(
+    dir: &Path,
+    prefix: &OsStr,
+    add: &mut impl FnMut(&OsStr, bool) -> bool,
+) -> Result<(), std::io::Error> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let mut name = prefix.to_os_string();
+        name.push(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            if add(&name, true) {
+                name.push("/");
+                add_files(&entry.path(), &name, add)?;
+            }
+        } else {
+            add(&name, false);
+        }
+    }
+    Ok(())
+}
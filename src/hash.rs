@@ -0,0 +1,172 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{FetchDataError, FetchDataSpecificError};
+
+/// The hash algorithms that can appear in a registry, following [Pooch](https://www.fatiando.org/pooch/latest/hashes.html).
+///
+/// In a registry, a hash may be prefixed by the name of its algorithm, for example, `md5:` or `xxh128:`.
+/// A hash without a prefix is SHA256.
+///
+/// # Example
+/// ```
+/// use fetch_data::HashAlgorithm;
+///
+/// let algorithm: HashAlgorithm = "md5".parse()?;
+/// assert_eq!(algorithm, HashAlgorithm::Md5);
+/// assert_eq!(HashAlgorithm::default(), HashAlgorithm::Sha256);
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// MD5, written `md5:` in a registry.
+    Md5,
+    /// SHA1, written `sha1:` in a registry.
+    Sha1,
+    /// SHA256, written `sha256:` (or with no prefix) in a registry. This is the default.
+    #[default]
+    Sha256,
+    /// SHA512, written `sha512:` in a registry.
+    Sha512,
+    /// 128-bit XXH3, written `xxh128:` in a registry.
+    Xxh128,
+}
+
+impl HashAlgorithm {
+    /// The name of the algorithm, as used in a registry prefix.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Xxh128 => "xxh128",
+        }
+    }
+
+    /// The number of hex digits in a hash from this algorithm.
+    #[must_use]
+    pub const fn hex_len(self) -> usize {
+        match self {
+            Self::Md5 | Self::Xxh128 => 32,
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+            Self::Sha512 => 128,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Box<FetchDataError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            "xxh128" => Ok(Self::Xxh128),
+            _ => Err(Box::new(
+                FetchDataSpecificError::UnknownHashAlgorithm(s.to_string()).into(),
+            )),
+        }
+    }
+}
+
+/// Split a registry hash such as `md5:abc...` into its algorithm and hex digest.
+/// A hash without a prefix is SHA256.
+pub fn split_hash(hash: &str) -> Result<(HashAlgorithm, &str), Box<FetchDataError>> {
+    match hash.split_once(':') {
+        Some((algorithm, hex)) => Ok((algorithm.parse()?, hex)),
+        None => Ok((HashAlgorithm::Sha256, hash)),
+    }
+}
+
+/// Whether two registry hashes are the same, for example, `sha256:<hex>` and `<hex>`: the same
+/// algorithm and the same digest, ignoring case.
+pub fn same_hash(hash: &str, other_hash: &str) -> bool {
+    match (split_hash(hash), split_hash(other_hash)) {
+        (Ok((algorithm, hex)), Ok((other_algorithm, other_hex))) => {
+            algorithm == other_algorithm && hex.eq_ignore_ascii_case(other_hex)
+        }
+        _ => hash.eq_ignore_ascii_case(other_hash),
+    }
+}
+
+/// Check that a registry hash has a known algorithm and the right number of hex digits.
+/// On failure, give the reason.
+pub fn check_hash_format(hash: &str) -> Result<(), String> {
+    let (algorithm, hex) = split_hash(hash).map_err(|e| e.to_string())?;
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("hash '{hex}' is not hexadecimal"));
+    }
+    if hex.len() != algorithm.hex_len() {
+        return Err(format!(
+            "{algorithm} hash has {} hex digits instead of {}",
+            hex.len(),
+            algorithm.hex_len()
+        ));
+    }
+    Ok(())
+}
+
+/// Incrementally computes the hash of bytes written to it.
+pub enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Xxh128(Box<Xxh3>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+            HashAlgorithm::Xxh128 => Self::Xxh128(Box::new(Xxh3::new())),
+        }
+    }
+
+    /// The lowercase hex digest of everything written so far.
+    pub fn finalize(self) -> String {
+        match self {
+            Self::Md5(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Self::Sha1(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Self::Sha256(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Self::Sha512(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Self::Xxh128(hasher) => format!("{:032x}", hasher.digest128()),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Md5(hasher) => hasher.update(buf),
+            Self::Sha1(hasher) => hasher.update(buf),
+            Self::Sha256(hasher) => hasher.update(buf),
+            Self::Sha512(hasher) => hasher.update(buf),
+            Self::Xxh128(hasher) => hasher.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
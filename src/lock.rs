@@ -0,0 +1,104 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use fs4::fs_std::FileExt;
+
+use crate::{FetchDataError, FetchDataSpecificError};
+
+/// An advisory lock on a local file, shared across processes.
+///
+/// The lock is an operating-system lock (`flock` or `LockFileEx`) held on a hidden sibling file,
+/// `.<file name>.lock`. The operating system releases it if the process dies, so a lock is never
+/// left behind. The lock file is never written to. On Unix, the process that created it removes
+/// it before unlocking, and a process that then gets the lock on the removed file notices and
+/// tries again. A lock file that this code didn't create is locked, but left in place.
+pub struct LockFile {
+    path: PathBuf,
+    file: File,
+    created: bool,
+}
+
+impl LockFile {
+    pub fn acquire(target: &Path, timeout: Duration) -> Result<Self, Box<FetchDataError>> {
+        let path = Self::lock_path(target)?;
+        let start = Instant::now();
+        loop {
+            let (file, created) = Self::open(&path).map_err(|e| Box::new(e.into()))?;
+            if file.try_lock_exclusive().map_err(|e| Box::new(e.into()))? {
+                if Self::is_current(&path, &file) {
+                    return Ok(Self {
+                        path,
+                        file,
+                        created,
+                    });
+                }
+                // The creator removed the file just before unlocking it. Lock a new one.
+                continue;
+            }
+            if start.elapsed() >= timeout {
+                return Err(Box::new(
+                    FetchDataSpecificError::LockTimeout(path.display().to_string()).into(),
+                ));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    // Open the lock file, creating it if needed, and tell whether it was created.
+    fn open(path: &Path) -> Result<(File, bool), io::Error> {
+        match File::options().write(true).create_new(true).open(path) {
+            Ok(file) => Ok((file, true)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                Ok((File::options().write(true).open(path)?, false))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // For example, `.small.bed.lock` for `small.bed`.
+    fn lock_path(target: &Path) -> Result<PathBuf, Box<FetchDataError>> {
+        let Some(file_name) = target.file_name() else {
+            return Err(Box::new(
+                FetchDataSpecificError::UnknownOrBadFile(target.display().to_string()).into(),
+            ));
+        };
+        let mut lock_name = OsString::from(".");
+        lock_name.push(file_name);
+        lock_name.push(".lock");
+        Ok(target.with_file_name(lock_name))
+    }
+
+    // Whether `file` is still the file at `path`.
+    #[cfg(unix)]
+    fn is_current(path: &Path, file: &File) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(path), file.metadata()) {
+            (Ok(at_path), Ok(locked)) => {
+                at_path.dev() == locked.dev() && at_path.ino() == locked.ino()
+            }
+            _ => false,
+        }
+    }
+
+    // Elsewhere, lock files are never removed, so the file is always current.
+    #[cfg(not(unix))]
+    const fn is_current(_path: &Path, _file: &File) -> bool {
+        true
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // Remove the file while still holding the lock, so no one can lock it in between.
+        if self.created && cfg!(unix) {
+            let _ = fs::remove_file(&self.path);
+        }
+        let _ = FileExt::unlock(&self.file);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+#[cfg(any(feature = "unpack", feature = "decompress"))]
+use crate::TempDownload;
+use crate::{slash_path, FetchDataError, FetchOptions};
+
+/// A step that turns a fetched file into other files, for example, by extracting or converting it.
+///
+/// Give a processor to [`FetchData::fetch_file_with`](crate::FetchData::fetch_file_with). Its result
+/// is remembered in the cache, keyed by the input file's registry hash and the processor's
+/// [`id`](Processor::id), so each output is produced only once, across runs and processes.
+/// With the `unpack` and `decompress` features, `Unpack` and `Decompress` are processors.
+///
+/// # Example
+/// ```
+/// use fetch_data::{FetchData, FetchDataError, Processor};
+/// use std::path::{Path, PathBuf};
+///
+/// // Write the number of lines in a file to a sibling `.count` file.
+/// struct CountLines;
+///
+/// impl Processor for CountLines {
+///     fn id(&self) -> String {
+///         "count-lines".to_string()
+///     }
+///
+///     fn process(&self, input: &Path, _cache_dir: &Path) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+///         let contents = std::fs::read_to_string(input).map_err(|e| Box::new(e.into()))?;
+///         let output = input.with_extension("count");
+///         std::fs::write(&output, contents.lines().count().to_string()).map_err(|e| Box::new(e.into()))?;
+///         Ok(vec![output])
+///     }
+/// }
+///
+/// let fetch_data = FetchData::new(
+///     include_str!("../registry.txt"),
+///     "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/",
+///     "BAR_APP_DATA_DIR",
+///     "com",
+///     "Foo Corp",
+///     "Bar App",
+/// );
+/// let output_list = fetch_data.fetch_file_with("small.fam", &CountLines)?;
+/// assert_eq!(std::fs::read_to_string(&output_list[0]).unwrap(), "3");
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+pub trait Processor {
+    /// Names the processor and any settings that change its output. If the output would change,
+    /// so must the id, so that old results aren't reused.
+    fn id(&self) -> String;
+
+    /// Produce output files from `input`, a fetched file whose hash was checked, and return their
+    /// paths. Outputs usually go next to `input`, inside `cache_dir`. They must not be files that
+    /// the registry lists, or the two would overwrite each other.
+    fn process(&self, input: &Path, cache_dir: &Path) -> Result<Vec<PathBuf>, Box<FetchDataError>>;
+
+    /// Choose the paths to return from the outputs of [`process`](Processor::process), whether it
+    /// just ran or its outputs were remembered. Settings used only here need not be part of the
+    /// [`id`](Processor::id), so they share one run. By default, all outputs are returned.
+    ///
+    /// # Errors
+    ///
+    /// If the wanted paths aren't among the outputs.
+    fn select(&self, output_list: Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+        Ok(output_list)
+    }
+}
+
+/// Run `processor` on `input`, unless it already ran on this version of the input. A record under
+/// `<cache_dir>/.processed` holds the input hash, the processor id, and the output paths. If the
+/// record matches and the outputs exist, they are reused without running the processor. Either
+/// way, [`Processor::select`] chooses which to return.
+pub fn process_once(
+    processor: &(impl Processor + ?Sized),
+    input: &Path,
+    input_hash: &str,
+    cache_dir: &Path,
+    options: &FetchOptions,
+) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+    let id = processor.id();
+    let record_path = record_path(input, &id, cache_dir);
+    if let Some(output_list) = read_record(&record_path, input_hash, &id, cache_dir) {
+        return processor.select(output_list);
+    }
+
+    // Another process may have finished while we waited for the lock.
+    let _lock_file = options.lock_file(&record_path)?;
+    if let Some(output_list) = read_record(&record_path, input_hash, &id, cache_dir) {
+        return processor.select(output_list);
+    }
+    let _ = fs::remove_file(&record_path);
+    let output_list = processor.process(input, cache_dir)?;
+
+    let mut record = format!("{input_hash}\n{}\n", id.replace('\n', " "));
+    for output in &output_list {
+        // Outputs outside the cache directory are recorded as they are, so that `read_record`
+        // finds them again.
+        match output.strip_prefix(cache_dir) {
+            Ok(relative_output) => record.push_str(&slash_path(relative_output)),
+            Err(_) => record.push_str(&output.to_string_lossy()),
+        }
+        record.push('\n');
+    }
+    fs::write(&record_path, record).map_err(|e| Box::new(e.into()))?;
+    processor.select(output_list)
+}
+
+// For example, `<cache_dir>/.processed/chr1/small.bed.gz.decompress-0123456789abcdef`.
+fn record_path(input: &Path, id: &str, cache_dir: &Path) -> PathBuf {
+    let relative_input = input.strip_prefix(cache_dir).unwrap_or(input);
+    let short_id: String = id
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .take(32)
+        .collect();
+    let mut file_name = relative_input
+        .file_name()
+        .unwrap_or_default()
+        .to_os_string();
+    file_name.push(format!(".{short_id}-{:016x}", xxh3_64(id.as_bytes())));
+    cache_dir
+        .join(".processed")
+        .join(slash_path(relative_input))
+        .with_file_name(file_name)
+}
+
+fn read_record(
+    record_path: &Path,
+    input_hash: &str,
+    id: &str,
+    cache_dir: &Path,
+) -> Option<Vec<PathBuf>> {
+    let record = fs::read_to_string(record_path).ok()?;
+    let mut lines = record.lines();
+    if lines.next()? != input_hash || lines.next()? != id.replace('\n', " ") {
+        return None;
+    }
+    let output_list: Vec<PathBuf> = lines.map(|line| cache_dir.join(line)).collect();
+    output_list
+        .iter()
+        .all(|output| output.exists())
+        .then_some(output_list)
+}
+
+/// Replace `output` (a file or directory) with what `produce` writes to a temporary sibling,
+/// so that a failure never leaves a partial output.
+#[cfg(any(feature = "unpack", feature = "decompress"))]
+pub fn replace_with(
+    output: &Path,
+    produce: impl FnOnce(&Path) -> Result<(), Box<FetchDataError>>,
+) -> Result<(), Box<FetchDataError>> {
+    let temp_path = TempDownload::sibling_temp_path(output)?;
+    remove_all(&temp_path).map_err(|e| Box::new(e.into()))?;
+    if let Err(e) = produce(&temp_path) {
+        let _ = remove_all(&temp_path);
+        return Err(e);
+    }
+    remove_all(output).map_err(|e| Box::new(e.into()))?;
+    fs::rename(&temp_path, output).map_err(|e| Box::new(e.into()))?;
+    Ok(())
+}
+
+// Remove a file or directory, if it exists.
+#[cfg(any(feature = "unpack", feature = "decompress"))]
+fn remove_all(path: &Path) -> Result<(), std::io::Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
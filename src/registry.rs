@@ -0,0 +1,456 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+use anyinput::anyinput;
+
+use crate::{
+    hash::{check_hash_format, same_hash, split_hash},
+    FetchDataError, FetchDataSpecificError, HashAlgorithm,
+};
+
+/// How registry contents changed, from [`diff_registry_contents`] or [`update_registry_file`].
+///
+/// # Example
+/// ```
+/// use fetch_data::diff_registry_contents;
+///
+/// // Stand-ins for real hashes.
+/// let [h1, h2, h3, h4, h5] = ["1", "2", "3", "4", "5"].map(|digit| digit.repeat(64));
+/// let old_contents =
+///     format!("# Reference data\n./a.bed sha256:{h1}\nb.bed {h2} # chromosome 2\nc.bed {h3}\n");
+/// let new_contents = format!("a.bed {h1}\nb.bed {h4}\nd.bed {h5}\n");
+/// let diff = diff_registry_contents(&old_contents, &new_contents)?;
+/// assert_eq!(diff.added, [("d.bed".to_string(), h5.clone())]);
+/// assert_eq!(diff.removed, [("c.bed".to_string(), h3.clone())]);
+/// assert_eq!(diff.changed, [("b.bed".to_string(), h2, h4.clone())]);
+/// assert_eq!(
+///     diff.contents(),
+///     format!("# Reference data\n./a.bed sha256:{h1}\nb.bed {h4} # chromosome 2\nd.bed {h5}\n")
+/// );
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryDiff {
+    /// Files that are new, with their hashes.
+    pub added: Vec<(String, String)>,
+    /// Files that are gone, with their old hashes.
+    pub removed: Vec<(String, String)>,
+    /// Files whose hash changed, with their old and new hashes.
+    pub changed: Vec<(String, String, String)>,
+    contents: String,
+}
+
+impl RegistryDiff {
+    /// The updated registry contents.
+    #[must_use]
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// True if nothing was added, removed, or changed.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare existing registry contents with new contents.
+///
+/// The new contents usually come from a generator:
+/// [`gen_registry_contents_from_dir`](crate::gen_registry_contents_from_dir) for local files, or
+/// [`FetchData::gen_registry_contents`](crate::FetchData::gen_registry_contents) for remote ones.
+/// For example, `diff_registry_contents(old, gen_registry_contents_from_dir(dir, file_list)?)`.
+///
+/// Files are matched by their normalized paths, so `./a.bed` and `a.bed` are the same file.
+/// Hashes are matched by algorithm and digest, so `sha256:<hex>` and `<hex>` are the same hash.
+///
+/// The updated contents keep the existing lines in order, with their comments and formatting.
+/// A changed hash is replaced in place (keeping an algorithm prefix, if the old hash had one),
+/// the lines of removed files are dropped, and added files are appended in the order of the new
+/// contents. See [`RegistryDiff`] for an example.
+#[anyinput]
+pub fn diff_registry_contents(
+    registry_contents: AnyString,
+    new_contents: AnyString,
+) -> Result<RegistryDiff, Box<FetchDataError>> {
+    let mut new_list = Vec::new();
+    let mut new_hashes = HashMap::new();
+    for (line_number, line) in (1..).zip(new_contents.lines()) {
+        let (name, hash) = match fields(line)[..] {
+            [] => continue,
+            [(_, name), (_, hash), ..] => (name, hash),
+            _ => return Err(line_problem(line_number, line, "missing hash".to_string())),
+        };
+        let name =
+            normalized_name(name).map_err(|reason| line_problem(line_number, line, reason))?;
+        check_hash_format(hash).map_err(|reason| line_problem(line_number, line, reason))?;
+        if new_hashes.insert(name.clone(), hash).is_none() {
+            new_list.push(name);
+        }
+    }
+
+    let mut diff = RegistryDiff::default();
+    let mut seen = HashSet::new();
+    for (line_number, line) in (1..).zip(registry_contents.split_inclusive('\n')) {
+        let [(_, name), (hash_start, hash), ..] = fields(line)[..] else {
+            diff.contents.push_str(line);
+            continue;
+        };
+        let name = normalized_name(name)
+            .map_err(|reason| line_problem(line_number, line.trim_end(), reason))?;
+        let Some(new_hash) = new_hashes.get(&name) else {
+            diff.removed.push((name, hash.to_string()));
+            continue;
+        };
+        if same_hash(hash, new_hash) {
+            diff.contents.push_str(line);
+        } else {
+            let new_hash = in_style_of(hash, new_hash);
+            diff.contents.push_str(&line[..hash_start]);
+            diff.contents.push_str(&new_hash);
+            diff.contents.push_str(&line[hash_start + hash.len()..]);
+            diff.changed
+                .push((name.clone(), hash.to_string(), new_hash));
+        }
+        seen.insert(name);
+    }
+
+    for name in new_list {
+        if seen.contains(&name) {
+            continue;
+        }
+        if !diff.contents.is_empty() && !diff.contents.ends_with('\n') {
+            diff.contents.push('\n');
+        }
+        let hash = new_hashes[&name];
+        writeln!(diff.contents, "{name} {hash}").expect("writing to a String never fails");
+        diff.added.push((name, hash.to_string()));
+    }
+    Ok(diff)
+}
+
+// A registry path with `/` separators and without `.` components.
+fn normalized_name(name: &str) -> Result<String, String> {
+    let path = crate::relative_path(name).map_err(|e| e.to_string())?;
+    if is_reserved(&path) {
+        return Err(RESERVED_REASON.to_string());
+    }
+    Ok(crate::slash_path(&path))
+}
+
+// Write `new_hash` with an algorithm prefix if `old_hash` had one.
+fn in_style_of(old_hash: &str, new_hash: &str) -> String {
+    match split_hash(new_hash) {
+        Ok((algorithm, hex)) if old_hash.contains(':') && !new_hash.contains(':') => {
+            format!("{algorithm}:{hex}")
+        }
+        _ => new_hash.to_string(),
+    }
+}
+
+fn line_problem(line_number: usize, line: &str, reason: String) -> Box<FetchDataError> {
+    Box::new(
+        FetchDataSpecificError::RegistryLineProblem(line_number, line.to_string(), reason).into(),
+    )
+}
+
+/// Like [`diff_registry_contents`], but read the existing contents from a file (if it exists)
+/// and, if anything changed, write the updated contents back to it.
+///
+/// # Example
+/// ```
+/// use fetch_data::{gen_registry_contents_from_dir, update_registry_file};
+/// use temp_testdir::TempDir;
+///
+/// let temp_dir = TempDir::default();
+/// let registry_path = temp_dir.join("registry.txt");
+/// let new_contents = gen_registry_contents_from_dir("tests/data", ["small.fam", "small.bim"])?;
+/// let diff = update_registry_file(&registry_path, &new_contents)?;
+/// assert_eq!(diff.added.len(), 2);
+/// let diff = update_registry_file(&registry_path, &new_contents)?;
+/// assert!(diff.is_empty());
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[anyinput]
+pub fn update_registry_file(
+    path: AnyPath,
+    new_contents: AnyString,
+) -> Result<RegistryDiff, Box<FetchDataError>> {
+    let registry_contents = match fs::read_to_string(path) {
+        Ok(registry_contents) => registry_contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Box::new(e.into())),
+    };
+    let diff = diff_registry_contents(&registry_contents, new_contents)?;
+    if !diff.is_empty() {
+        fs::write(path, diff.contents()).map_err(|e| Box::new(e.into()))?;
+    }
+    Ok(diff)
+}
+
+// Processors write their outputs and records to `<file>.unpacked`, `<file>.decompressed`, and
+// `.processed`, so the registry can't list those paths, or it and a processor would overwrite
+// each other's files.
+const OUTPUT_SUFFIX_LIST: [&str; 2] = [".unpacked", ".decompressed"];
+const RECORD_DIR: &str = ".processed";
+
+/// Why a reserved registry path is a problem.
+pub const RESERVED_REASON: &str =
+    "path is reserved for processor outputs: `.processed`, `*.unpacked`, or `*.decompressed`";
+
+/// Whether a (normalized, relative) registry path is reserved for processor outputs.
+pub fn is_reserved(path: &Path) -> bool {
+    path.iter().enumerate().any(|(index, component)| {
+        let component = component.to_string_lossy().to_ascii_lowercase();
+        (index == 0 && component == RECORD_DIR)
+            || OUTPUT_SUFFIX_LIST
+                .iter()
+                .any(|suffix| component.ends_with(suffix))
+    })
+}
+
+/// The whitespace-separated fields of a registry line, with their byte offsets. A `#` at the
+/// start of a field begins a comment, which is ignored.
+pub fn fields(line: &str) -> Vec<(usize, &str)> {
+    let mut field_list = Vec::new();
+    let mut start = None;
+    for (index, c) in line.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = start.take() {
+                field_list.push((start, &line[start..index]));
+            }
+        } else if start.is_none() {
+            if c == '#' {
+                return field_list;
+            }
+            start = Some(index);
+        }
+    }
+    if let Some(start) = start {
+        field_list.push((start, &line[start..]));
+    }
+    field_list
+}
+
+/// Include a registry file as a `&'static str`, like [`include_str!`], but check it at compile time.
+///
+/// The checks are the same as when [`FetchData`](crate::FetchData) loads a registry: each line
+/// has a relative path outside the directories reserved for processor outputs, a hash of the
+/// right length for its algorithm, and an optional URL. A problem is a compile error. A path listed twice with different hashes is reported when the
+/// registry is loaded, not at compile time.
+///
+/// # Example
+/// ```
+/// use fetch_data::{include_registry, FetchData};
+///
+/// let fetch_data = FetchData::new(
+///     include_registry!("../registry.txt"),
+///     "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/",
+///     "BAR_APP_DATA_DIR",
+///     "com",
+///     "Foo Corp",
+///     "Bar App",
+/// );
+/// ```
+///
+/// ```compile_fail
+/// const REGISTRY: &str = fetch_data::include_registry!("../README.md");
+/// ```
+#[macro_export]
+macro_rules! include_registry {
+    ($path:expr) => {{
+        const CONTENTS: &str = include_str!($path);
+        const _: () = if let Some(reason) = $crate::registry_problem(CONTENTS) {
+            panic!("{}", reason);
+        };
+        CONTENTS
+    }};
+}
+
+/// The first problem with registry contents, if any, for [`include_registry!`].
+///
+/// This is a `const fn` version of the checks made at run time, without line numbers. It doesn't
+/// look for paths listed twice, because that would take time quadratic in the number of lines.
+#[must_use]
+pub const fn registry_problem(registry_contents: &str) -> Option<&'static str> {
+    let bytes = registry_contents.as_bytes();
+    let mut line_start = 0;
+    while line_start < bytes.len() {
+        let line_end = line_end(bytes, line_start);
+        let (field_list, count) = line_fields(bytes, line_start, line_end);
+        match count {
+            0 => {}
+            1 => return Some("registry line is missing a hash"),
+            2 | 3 => {
+                if let Some(problem) = path_problem(bytes, field_list[0]) {
+                    return Some(problem);
+                }
+                if let Some(problem) = hash_problem(bytes, field_list[1]) {
+                    return Some(problem);
+                }
+            }
+            _ => return Some("registry line has an extra column"),
+        }
+        line_start = line_end + 1;
+    }
+    None
+}
+
+const fn line_end(bytes: &[u8], line_start: usize) -> usize {
+    let mut index = line_start;
+    while index < bytes.len() && bytes[index] != b'\n' {
+        index += 1;
+    }
+    index
+}
+
+// Like `fields`, as (start, end) pairs. Stops counting after four.
+const fn line_fields(bytes: &[u8], start: usize, end: usize) -> ([(usize, usize); 4], usize) {
+    let mut field_list = [(0, 0); 4];
+    let mut count = 0;
+    let mut index = start;
+    while index < end && count < 4 {
+        if bytes[index].is_ascii_whitespace() {
+            index += 1;
+            continue;
+        }
+        if bytes[index] == b'#' {
+            break;
+        }
+        let field_start = index;
+        while index < end && !bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+        field_list[count] = (field_start, index);
+        count += 1;
+    }
+    (field_list, count)
+}
+
+const fn path_problem(bytes: &[u8], (start, end): (usize, usize)) -> Option<&'static str> {
+    let is_absolute = bytes[start] == b'/' || bytes[start] == b'\\';
+    let has_drive_prefix =
+        end - start >= 2 && bytes[start].is_ascii_alphabetic() && bytes[start + 1] == b':';
+    if is_absolute || has_drive_prefix {
+        return Some("registry path is absolute");
+    }
+    let mut component_start = start;
+    let mut index = start;
+    while index <= end {
+        if index == end || bytes[index] == b'/' || bytes[index] == b'\\' {
+            if index - component_start == 2
+                && bytes[component_start] == b'.'
+                && bytes[component_start + 1] == b'.'
+            {
+                return Some("registry path leaves the cache directory");
+            }
+            if is_reserved_component(bytes, (component_start, index), component_start == start) {
+                return Some("registry path is reserved for processor outputs");
+            }
+            component_start = index + 1;
+        }
+        index += 1;
+    }
+    None
+}
+
+// Like `is_reserved`, for one component.
+const fn is_reserved_component(bytes: &[u8], (start, end): (usize, usize), is_first: bool) -> bool {
+    let record_dir = RECORD_DIR.as_bytes();
+    if is_first && bytes_eq(bytes, (start, end), record_dir, (0, record_dir.len()), true) {
+        return true;
+    }
+    let mut index = 0;
+    while index < OUTPUT_SUFFIX_LIST.len() {
+        let suffix = OUTPUT_SUFFIX_LIST[index].as_bytes();
+        if end - start >= suffix.len()
+            && bytes_eq(
+                bytes,
+                (end - suffix.len(), end),
+                suffix,
+                (0, suffix.len()),
+                true,
+            )
+        {
+            return true;
+        }
+        index += 1;
+    }
+    false
+}
+
+const fn hash_problem(bytes: &[u8], (start, end): (usize, usize)) -> Option<&'static str> {
+    let mut hex_start = start;
+    while hex_start < end && bytes[hex_start] != b':' {
+        hex_start += 1;
+    }
+    let hex_len = if hex_start == end {
+        hex_start = start;
+        HashAlgorithm::Sha256.hex_len()
+    } else {
+        let Some(algorithm) = algorithm(bytes, start, hex_start) else {
+            return Some("registry hash has an unknown algorithm");
+        };
+        hex_start += 1;
+        algorithm.hex_len()
+    };
+    let mut index = hex_start;
+    while index < end {
+        if !bytes[index].is_ascii_hexdigit() {
+            return Some("registry hash is not hexadecimal");
+        }
+        index += 1;
+    }
+    if end - hex_start != hex_len {
+        return Some("registry hash has the wrong length for its algorithm");
+    }
+    None
+}
+
+const fn algorithm(bytes: &[u8], start: usize, end: usize) -> Option<HashAlgorithm> {
+    let algorithm_list = [
+        HashAlgorithm::Md5,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Xxh128,
+    ];
+    let mut index = 0;
+    while index < algorithm_list.len() {
+        let name = algorithm_list[index].name().as_bytes();
+        if bytes_eq(bytes, (start, end), name, (0, name.len()), true) {
+            return Some(algorithm_list[index]);
+        }
+        index += 1;
+    }
+    None
+}
+
+const fn bytes_eq(
+    left: &[u8],
+    (left_start, left_end): (usize, usize),
+    right: &[u8],
+    (right_start, right_end): (usize, usize),
+    ignore_case: bool,
+) -> bool {
+    if left_end - left_start != right_end - right_start {
+        return false;
+    }
+    let mut index = 0;
+    while index < left_end - left_start {
+        let (left_byte, right_byte) = (left[left_start + index], right[right_start + index]);
+        if left_byte != right_byte && !(ignore_case && left_byte.eq_ignore_ascii_case(&right_byte))
+        {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
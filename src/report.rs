@@ -0,0 +1,98 @@
+use std::{fmt, path::PathBuf};
+
+use crate::FetchDataError;
+
+/// What happened to each file, from [`FetchData::fetch_all`](crate::FetchData::fetch_all) or
+/// [`FetchData::fetch_matching`](crate::FetchData::fetch_matching).
+///
+/// # Example
+/// ```
+/// use fetch_data::FetchData;
+///
+/// let fetch_data = FetchData::new(
+///     include_str!("../registry.txt"),
+///     "https://raw.githubusercontent.com/CarlKCarlK/fetch-data/main/tests/data/",
+///     "BAR_APP_DATA_DIR",
+///     "com",
+///     "Foo Corp",
+///     "Bar App",
+/// );
+/// let report = fetch_data.fetch_matching("*.fam")?;
+/// println!("{report}"); // For example, "1 files: 0 already cached, 1 downloaded (24 bytes), 0 failed"
+/// for failure in report.failures() {
+///     eprintln!("{}: {:?}", failure.path, failure.status);
+/// }
+/// assert!(report.is_success());
+/// # use fetch_data::FetchDataError;
+/// # Ok::<(), Box<FetchDataError>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct FetchReport {
+    /// One entry per file, sorted by path.
+    pub files: Vec<FileReport>,
+}
+
+/// One file's entry in a [`FetchReport`].
+#[derive(Debug)]
+pub struct FileReport {
+    /// The file's registry path, with `/` separators.
+    pub path: String,
+    /// Where the file is, or would be, in the cache.
+    pub local_path: PathBuf,
+    /// What happened to the file.
+    pub status: FetchStatus,
+    /// The bytes received while downloading the file. Zero unless it was downloaded.
+    pub bytes_transferred: u64,
+}
+
+/// Whether a file in a [`FetchReport`] was already cached, downloaded, or failed.
+#[derive(Debug)]
+pub enum FetchStatus {
+    /// The file was already in the cache, with the right hash.
+    AlreadyCached,
+    /// The file was downloaded and its hash checked.
+    Downloaded,
+    /// The file could not be fetched.
+    Failed(Box<FetchDataError>),
+}
+
+impl FetchReport {
+    /// The bytes received, summed over all files.
+    #[must_use]
+    pub fn bytes_transferred(&self) -> u64 {
+        self.files.iter().map(|file| file.bytes_transferred).sum()
+    }
+
+    /// The files that could not be fetched.
+    pub fn failures(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FetchStatus::Failed(_)))
+    }
+
+    /// True if no file failed.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for FetchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |predicate: fn(&FetchStatus) -> bool| {
+            self.files
+                .iter()
+                .filter(|file| predicate(&file.status))
+                .count()
+        };
+        write!(
+            f,
+            "{} files: {} already cached, {} downloaded ({} bytes), {} failed",
+            self.files.len(),
+            count(|status| matches!(status, FetchStatus::AlreadyCached)),
+            count(|status| matches!(status, FetchStatus::Downloaded)),
+            self.bytes_transferred(),
+            count(|status| matches!(status, FetchStatus::Failed(_))),
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::{Duration, SystemTime};
+
+/// How downloads retry transient HTTP failures, with exponential backoff.
+///
+/// A download is retried after a connection failure, a dropped connection, or a response whose
+/// status code is in the retry list (by default, 408, 429, 500, 502, 503, and 504). Before retry
+/// `n` (starting at 0), it waits `base_delay * 2^n`, capped at `max_delay`. With jitter, the wait is
+/// randomly shortened by up to half, so that many clients don't retry in lockstep. If the server
+/// sends a `Retry-After` header, its wait (also capped at `max_delay`) is used instead.
+///
+/// # Example
+/// ```
+/// use fetch_data::{FetchOptions, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let retry_policy = RetryPolicy::new()
+///     .max_attempts(6)
+///     .base_delay(Duration::from_secs(1))
+///     .retry_status_codes([429, 503]);
+/// let options = FetchOptions::new().retry_policy(retry_policy);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_status_codes: Vec<u16>,
+    respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_status_codes: vec![408, 429, 500, 502, 503, 504],
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a retry policy with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that never retries.
+    #[must_use]
+    pub fn none() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Set the total number of attempts, including the first. Defaults to 4. A value of 0 is treated as 1.
+    #[must_use]
+    pub const fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the wait before the first retry. Later retries wait twice as long as the one before. Defaults to 500 ms.
+    #[must_use]
+    pub const fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Set the longest wait between attempts. Defaults to 30 seconds.
+    #[must_use]
+    pub const fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set whether to randomly shorten each wait by up to half. Defaults to `true`.
+    #[must_use]
+    pub const fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the HTTP status codes that are retried. Defaults to 408, 429, 500, 502, 503, and 504.
+    #[must_use]
+    pub fn retry_status_codes(mut self, retry_status_codes: impl IntoIterator<Item = u16>) -> Self {
+        self.retry_status_codes = retry_status_codes.into_iter().collect();
+        self
+    }
+
+    /// Set whether to wait as long as a response's `Retry-After` header asks. Defaults to `true`.
+    #[must_use]
+    pub const fn respect_retry_after(mut self, respect_retry_after: bool) -> Self {
+        self.respect_retry_after = respect_retry_after;
+        self
+    }
+
+    /// If the failed `attempt` (starting at 0) should be retried, how long to wait first.
+    pub(crate) fn delay_before_retry(&self, attempt: u32, error: &ureq::Error) -> Option<Duration> {
+        if attempt + 1 >= self.max_attempts.max(1) {
+            return None;
+        }
+        let retry_after = match error {
+            ureq::Error::Status(code, response) if self.retry_status_codes.contains(code) => {
+                response.header("Retry-After")
+            }
+            ureq::Error::Transport(transport)
+                if matches!(
+                    transport.kind(),
+                    ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io
+                ) =>
+            {
+                None
+            }
+            _ => return None,
+        };
+        if let Some(retry_after) = retry_after
+            .filter(|_| self.respect_retry_after)
+            .and_then(parse_retry_after)
+        {
+            return Some(retry_after.min(self.max_delay));
+        }
+        Some(self.backoff(attempt))
+    }
+
+    /// How long to wait before reconnecting after the failed `attempt` dropped its connection.
+    pub(crate) fn delay_before_resume(&self, attempt: u32) -> Option<Duration> {
+        (attempt + 1 < self.max_attempts.max(1)).then(|| self.backoff(attempt))
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(random_fraction().mul_add(0.5, 0.5))
+        } else {
+            delay
+        }
+    }
+}
+
+// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+// A random number in [0, 1).
+fn random_fraction() -> f64 {
+    let mut bytes = [0u8; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64;
+    fraction
+}
@@ -0,0 +1,235 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use anyinput::anyinput;
+
+use crate::{processor, FetchDataError, FetchDataSpecificError, Processor};
+
+/// The archive formats that [`Unpack`] can extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+    /// A `.zip` file.
+    Zip,
+    /// A gzip-compressed tar file, `.tar.gz` or `.tgz`.
+    TarGz,
+    /// A zstd-compressed tar file, `.tar.zst` or `.tzst`.
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// The format of an archive, from its file name.
+    #[must_use]
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_ascii_lowercase();
+        [
+            (".zip", Self::Zip),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+        ]
+        .into_iter()
+        .find_map(|(suffix, format)| file_name.ends_with(suffix).then_some(format))
+    }
+}
+
+/// How [`FetchData::fetch_unpacked`](crate::FetchData::fetch_unpacked) extracts an archive.
+///
+/// The archive is extracted, once, into a sibling directory named `<archive name>.unpacked`.
+/// It is extracted again only if the archive's registry hash or the [`format`](Unpack::format)
+/// setting changes. Settings that differ only in [`members`](Unpack::members) share one
+/// extraction, and the members are chosen from it. A registry can't list paths ending with
+/// `.unpacked`. Members whose paths would land outside that directory (zip-slip) are an error.
+/// Links are not extracted.
+///
+/// # Example
+/// ```
+/// use fetch_data::{ArchiveFormat, Unpack};
+///
+/// // Return the path of one member, rather than the directory.
+/// let unpack = Unpack::new().members(["data/small.bed"]);
+/// // Give the format when the file name doesn't end with `.zip`, `.tar.gz`, etc.
+/// let unpack = Unpack::new().format(ArchiveFormat::TarGz);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unpack {
+    format: Option<ArchiveFormat>,
+    members: Option<Vec<String>>,
+}
+
+impl Unpack {
+    /// Extract the whole archive and return the directory. The format comes from the file name.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the archive format, rather than going by the file name.
+    #[must_use]
+    pub const fn format(mut self, format: ArchiveFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Return the paths of these members (relative paths with `/` separators), rather than the directory.
+    #[anyinput]
+    #[must_use]
+    pub fn members(mut self, members: AnyIter<AnyString>) -> Self {
+        self.members = Some(members.map(|member| member.as_ref().to_string()).collect());
+        self
+    }
+
+    fn output_dir(archive: &Path) -> PathBuf {
+        let mut file_name = archive.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".unpacked");
+        archive.with_file_name(file_name)
+    }
+
+    fn extract(&self, archive: &Path, dir: &Path) -> Result<(), Box<FetchDataError>> {
+        let archive_name = archive.display().to_string();
+        let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+        let Some(format) = self
+            .format
+            .or_else(|| ArchiveFormat::from_file_name(&file_name))
+        else {
+            return Err(Box::new(
+                FetchDataSpecificError::UnknownArchiveFormat(archive_name).into(),
+            ));
+        };
+        fs::create_dir_all(dir).map_err(|e| Box::new(e.into()))?;
+        let file = BufReader::new(File::open(archive).map_err(|e| Box::new(e.into()))?);
+        let problem = |reason: String| -> Box<FetchDataError> {
+            Box::new(FetchDataSpecificError::ArchiveProblem(archive_name.clone(), reason).into())
+        };
+        match format {
+            ArchiveFormat::Zip => extract_zip(file, dir, &problem),
+            ArchiveFormat::TarGz => extract_tar(flate2::read::GzDecoder::new(file), dir, &problem),
+            ArchiveFormat::TarZst => {
+                let decoder = ruzstd::decoding::StreamingDecoder::new(file)
+                    .map_err(|e| problem(e.to_string()))?;
+                extract_tar(decoder, dir, &problem)
+            }
+        }
+    }
+}
+
+impl Processor for Unpack {
+    // The members are left out, so that every selection shares one extraction and one lock.
+    fn id(&self) -> String {
+        format!("unpack {:?}", self.format)
+    }
+
+    fn process(
+        &self,
+        input: &Path,
+        _cache_dir: &Path,
+    ) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+        let output_dir = Self::output_dir(input);
+        processor::replace_with(&output_dir, |temp_dir| self.extract(input, temp_dir))?;
+        Ok(vec![output_dir])
+    }
+
+    // The selected members, or else the directory.
+    fn select(&self, output_list: Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+        let (Some(members), [dir]) = (&self.members, &output_list[..]) else {
+            return Ok(output_list);
+        };
+        members
+            .iter()
+            .map(|member| {
+                let path = member_path(dir, Path::new(member))
+                    .filter(|path| path.is_file())
+                    .ok_or_else(|| {
+                        Box::new(FetchDataSpecificError::UnknownOrBadFile(member.clone()).into())
+                    })?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+// Where a member goes under `dir`, or `None` if it would land outside (zip-slip).
+fn member_path(dir: &Path, member: &Path) -> Option<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for component in member.components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (path != dir).then_some(path)
+}
+
+fn unsafe_member(
+    problem: &impl Fn(String) -> Box<FetchDataError>,
+    member: &Path,
+) -> Box<FetchDataError> {
+    problem(format!(
+        "member '{}' would be extracted outside the directory",
+        member.display()
+    ))
+}
+
+fn extract_zip(
+    file: impl Read + io::Seek,
+    dir: &Path,
+    problem: &impl Fn(String) -> Box<FetchDataError>,
+) -> Result<(), Box<FetchDataError>> {
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| problem(e.to_string()))?;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| problem(e.to_string()))?;
+        let member = PathBuf::from(entry.name());
+        let Some(path) = member_path(dir, &member) else {
+            return Err(unsafe_member(problem, &member));
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(&path).map_err(|e| Box::new(e.into()))?;
+        } else if entry.is_file() {
+            write_member(&mut entry, &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_tar(
+    reader: impl Read,
+    dir: &Path,
+    problem: &impl Fn(String) -> Box<FetchDataError>,
+) -> Result<(), Box<FetchDataError>> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(|e| problem(e.to_string()))? {
+        let mut entry = entry.map_err(|e| problem(e.to_string()))?;
+        let member = entry
+            .path()
+            .map_err(|e| problem(e.to_string()))?
+            .into_owned();
+        let Some(path) = member_path(dir, &member) else {
+            return Err(unsafe_member(problem, &member));
+        };
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                fs::create_dir_all(&path).map_err(|e| Box::new(e.into()))?;
+            }
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                write_member(&mut entry, &path)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn write_member(reader: &mut impl Read, path: &Path) -> Result<(), Box<FetchDataError>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Box::new(e.into()))?;
+    }
+    let mut file = File::create(path).map_err(|e| Box::new(e.into()))?;
+    io::copy(reader, &mut file).map_err(|e| Box::new(e.into()))?;
+    Ok(())
+}
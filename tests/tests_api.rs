@@ -1,10 +1,26 @@
-use std::path::PathBuf;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use anyinput::anyinput;
 use fetch_data::{
-    ctor, dir_to_file_list, download, fetch, hash_download, hash_file, FetchData, FetchDataError,
-    FetchDataSpecificError,
+    ctor, diff_registry_contents, dir_to_file_list, dir_to_file_list_recursive,
+    dir_to_file_list_with_options, download, download_with_options, fetch, fetch_with_options,
+    gen_registry_contents_from_dir, hash_download, hash_file, hash_file_with, FetchData,
+    FetchDataError, FetchDataSpecificError, FetchOptions, FetchStatus, FileListOptions,
+    HashAlgorithm, MismatchPolicy, Processor, RetryPolicy,
 };
+#[cfg(feature = "unpack")]
+use fetch_data::{ArchiveFormat, Unpack};
+#[cfg(feature = "decompress")]
+use fetch_data::{CompressionFormat, Decompress};
 use temp_testdir::TempDir;
 
 #[test]
@@ -142,6 +158,1392 @@ fn one_off_just_dir_to_file_list() -> Result<(), Box<FetchDataError>> {
     Ok(())
 }
 
+#[test]
+fn failed_fetch_leaves_no_file() -> Result<(), Box<FetchDataError>> {
+    let url_root = serve_test_data();
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("small.fam");
+
+    let wrong_hash = "0".repeat(64);
+    let result = fetch(format!("{url_root}small.fam"), &wrong_hash, &path);
+    assert!(matches!(
+        result.map_err(|e| *e),
+        Err(FetchDataError::FetchDataError(
+            FetchDataSpecificError::DownloadedFileWrongHash(..)
+        ))
+    ));
+    assert!(download(format!("{url_root}no_such_file"), &path).is_err());
+    // Neither the final file nor any temporary file is left behind.
+    assert!(dir_to_file_list(&temp_dir)?.is_empty());
+
+    let hash = download(format!("{url_root}small.fam"), &path)?;
+    assert_eq!(
+        hash,
+        "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2"
+    );
+    assert_eq!(dir_to_file_list(&temp_dir)?, ["small.fam"]);
+    Ok(())
+}
+
+#[test]
+fn redownload_on_hash_mismatch() -> Result<(), anyhow::Error> {
+    let url_root = serve_test_data();
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("small.fam");
+    let url = format!("{url_root}small.fam");
+    let hash = "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2";
+
+    std::fs::write(&path, "stale contents")?;
+    assert!(fetch(&url, hash, &path).is_err());
+
+    let keep_stale = FetchOptions::new().mismatch_policy(MismatchPolicy::RedownloadKeepStale);
+    fetch_with_options(&url, hash, &path, &keep_stale)?;
+    assert_eq!(hash_file(&path)?, hash);
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.join("small.fam.stale"))?,
+        "stale contents"
+    );
+
+    // If the fresh download is also wrong, the error is still reported.
+    let redownload = FetchOptions::new().mismatch_policy(MismatchPolicy::Redownload);
+    let result = fetch_with_options(&url, "0".repeat(64), &path, &redownload);
+    assert!(matches!(
+        result.map_err(|e| *e),
+        Err(FetchDataError::FetchDataError(
+            FetchDataSpecificError::DownloadedFileWrongHash(..)
+        ))
+    ));
+    Ok(())
+}
+
+#[test]
+fn fetch_data_redownloads_on_hash_mismatch() -> Result<(), anyhow::Error> {
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(
+            "small.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2",
+        )
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .mismatch_policy(MismatchPolicy::Redownload)
+        .build();
+
+    std::fs::write(temp_dir.join("small.fam"), "stale contents")?;
+    let local_path = fetch_data.fetch_file("small.fam")?;
+    assert_eq!(std::fs::metadata(local_path)?.len(), 85);
+    Ok(())
+}
+
+#[test]
+fn multi_algorithm_hashes() -> Result<(), Box<FetchDataError>> {
+    assert_eq!(
+        hash_file_with("tests/data/small.fam", HashAlgorithm::Md5)?,
+        "e420f5886483002b2fad03a0e352b37f"
+    );
+    assert_eq!(
+        hash_file_with("tests/data/empty.bed", HashAlgorithm::Xxh128)?,
+        "99aa06d3014798d86001c324468d497f"
+    );
+
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(
+            "small.fam md5:e420f5886483002b2fad03a0e352b37f
+             small.bim sha256:56b6657a3766e2e52273f89d28be6135f9424ca1d204d29f3fa1c5a90eca794e
+             empty.bed xxh128:99aa06d3014798d86001c324468d497f
+             small.bed sha1:0000000000000000000000000000000000000000",
+        )
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    fetch_data.fetch_files(["small.fam", "small.bim", "empty.bed"])?;
+    assert!(fetch_data.fetch_file("small.bed").is_err());
+
+    let bad_algorithm =
+        FetchData::new("small.fam crc32:1234", "", "", "com", "Foo Corp", "Bar App");
+    assert!(bad_algorithm.fetch_file("small.fam").is_err());
+    Ok(())
+}
+
+#[test]
+fn parallel_fetch_files() -> Result<(), Box<FetchDataError>> {
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .max_concurrent_downloads(3)
+        .build();
+
+    let file_list = [
+        "small.bed",
+        "small.bim",
+        "small.fam",
+        "empty.bed",
+        "small.bim",
+    ];
+    let local_list = fetch_data.fetch_files(file_list)?;
+    assert_eq!(local_list.len(), file_list.len());
+    for (local_path, file) in local_list.iter().zip(file_list) {
+        assert_eq!(local_path, &temp_dir.join(file));
+        assert_eq!(
+            hash_file(local_path)?,
+            hash_file(format!("tests/data/{file}"))?
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn parallel_fetch_files_failures() {
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(
+            "small.fam 0000000000000000000000000000000000000000000000000000000000000000
+             small.bim 56b6657a3766e2e52273f89d28be6135f9424ca1d204d29f3fa1c5a90eca794e
+             small.bed 0000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+
+    let result = fetch_data.fetch_files(["small.fam", "small.bim", "small.bed"]);
+    let Err(e) = result else {
+        panic!("test failure");
+    };
+    let FetchDataError::FetchFilesFailed(failures) = *e else {
+        panic!("test failure");
+    };
+    let failed: Vec<_> = failures.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(
+        failed,
+        [temp_dir.join("small.fam"), temp_dir.join("small.bed")]
+    );
+    // The file that succeeded is still in the cache.
+    assert!(temp_dir.join("small.bim").exists());
+}
+
+#[test]
+fn same_file_from_many_threads_downloads_once() -> Result<(), Box<FetchDataError>> {
+    let temp_dir = TempDir::default();
+    let (url_root, request_count) = serve_test_data_counted();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(url_root)
+        .cache_dir(&temp_dir)
+        .build();
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| scope.spawn(|| fetch_data.fetch_file("small.bed")))
+            .collect();
+        for handle in handles {
+            assert!(handle.join().expect("thread panicked").is_ok());
+        }
+    });
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn instances_sharing_a_cache_dir_download_once() -> Result<(), Box<FetchDataError>> {
+    // Separate instances coordinate only through lock files, just as separate processes do.
+    let temp_dir = TempDir::default();
+    let (url_root, request_count) = serve_test_data_counted();
+    let new_instance = || {
+        FetchData::builder()
+            .registry_contents(include_str!("../registry.txt"))
+            .url_root(&url_root)
+            .cache_dir(&temp_dir)
+            .build()
+    };
+    let instance_list = [new_instance(), new_instance(), new_instance()];
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = instance_list
+            .iter()
+            .map(|fetch_data| scope.spawn(|| fetch_data.fetch_file("small.bed")))
+            .collect();
+        for handle in handles {
+            assert!(handle.join().expect("thread panicked").is_ok());
+        }
+    });
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    assert_eq!(dir_to_file_list(&temp_dir)?, ["small.bed"]);
+    Ok(())
+}
+
+#[test]
+fn lock_file_timeout_and_release() -> Result<(), anyhow::Error> {
+    use fs4::fs_std::FileExt;
+
+    let url_root = serve_test_data();
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("small.fam");
+    let url = format!("{url_root}small.fam");
+    let hash = "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2";
+    let options = FetchOptions::new().lock_timeout(Duration::from_millis(200));
+
+    // A lock file left by a process that died doesn't block anyone, and isn't changed.
+    let lock_path = temp_dir.join(".other.fam.lock");
+    std::fs::write(&lock_path, "12345")?;
+    fetch_with_options(&url, hash, temp_dir.join("other.fam"), &options)?;
+    assert_eq!(std::fs::read_to_string(&lock_path)?, "12345");
+
+    // A lock file this code creates is removed afterward (on Unix), and an unrelated file with
+    // a `.lock` name is never touched, even when the download fails.
+    std::fs::write(temp_dir.join("Cargo.lock"), "unrelated")?;
+    assert!(
+        download_with_options("http://127.0.0.1:1/Cargo", temp_dir.join("Cargo"), &options)
+            .is_err()
+    );
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.join("Cargo.lock"))?,
+        "unrelated"
+    );
+    #[cfg(unix)]
+    assert!(!temp_dir.join(".Cargo.lock").exists());
+
+    // Another process is downloading the file.
+    let lock_path = temp_dir.join(".small.fam.lock");
+    let lock_file = std::fs::File::create(&lock_path)?;
+    lock_file.lock_exclusive()?;
+    let result = fetch_with_options(&url, hash, &path, &options);
+    assert!(matches!(
+        result.map_err(|e| *e),
+        Err(FetchDataError::FetchDataError(
+            FetchDataSpecificError::LockTimeout(_)
+        ))
+    ));
+
+    // When that process ends, its lock is released.
+    drop(lock_file);
+    fetch_with_options(&url, hash, &path, &options)?;
+    assert!(path.exists());
+    Ok(())
+}
+
+#[test]
+fn resume_dropped_download() -> Result<(), anyhow::Error> {
+    let bytes = std::fs::read("tests/data/plink_sim_10s_100v_10pmiss.bed")?;
+    let half = bytes.len() / 2;
+    let range_list = Arc::new(Mutex::new(Vec::new()));
+    let url_root = serve_raw({
+        let range_list = Arc::clone(&range_list);
+        move |request_head, mut stream| {
+            let range_start = range_start(request_head);
+            range_list.lock().expect("lock").push(range_start);
+            if let Some(start) =
+                range_start.filter(|_| header(request_head, "If-Range") == Some("\"v1\""))
+            {
+                write_response(
+                    &mut stream,
+                    "206 Partial Content",
+                    &bytes[start..],
+                    |head| {
+                        format!(
+                            "{head}Content-Range: bytes {start}-{}/{}\r\n",
+                            bytes.len() - 1,
+                            bytes.len()
+                        )
+                    },
+                );
+            } else {
+                // Promise the whole file, but drop the connection halfway through.
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                    bytes.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&bytes[..half]);
+            }
+        }
+    });
+
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("plink_sim_10s_100v_10pmiss.bed");
+    fetch(
+        format!("{url_root}plink_sim_10s_100v_10pmiss.bed"),
+        "8364a0ba9290b1ea86b609dc42e3611458aeda7bea16cc83c7752fd3698bb2ba",
+        &path,
+    )?;
+    assert_eq!(*range_list.lock().expect("lock"), [None, Some(half)]);
+    assert_eq!(
+        dir_to_file_list(&temp_dir)?,
+        ["plink_sim_10s_100v_10pmiss.bed"]
+    );
+
+    // A partial file left by an earlier call is resumed, and its hash still covers the whole file.
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("plink_sim_10s_100v_10pmiss.bed");
+    std::fs::write(
+        temp_dir.join(".plink_sim_10s_100v_10pmiss.bed.part"),
+        &std::fs::read("tests/data/plink_sim_10s_100v_10pmiss.bed")?[..100],
+    )?;
+    std::fs::write(
+        temp_dir.join(".plink_sim_10s_100v_10pmiss.bed.part.validator"),
+        "\"v1\"",
+    )?;
+    fetch(
+        format!("{url_root}plink_sim_10s_100v_10pmiss.bed"),
+        "8364a0ba9290b1ea86b609dc42e3611458aeda7bea16cc83c7752fd3698bb2ba",
+        &path,
+    )?;
+    assert_eq!(range_list.lock().expect("lock").last(), Some(&Some(100)));
+    assert_eq!(
+        dir_to_file_list(&temp_dir)?,
+        ["plink_sim_10s_100v_10pmiss.bed"]
+    );
+
+    // Without a hash to check, `download` doesn't trust a partial file left by an earlier call.
+    std::fs::write(
+        temp_dir.join(".plink_sim_10s_100v_10pmiss.bed.part"),
+        "OLD VERSION",
+    )?;
+    std::fs::write(
+        temp_dir.join(".plink_sim_10s_100v_10pmiss.bed.part.validator"),
+        "\"v1\"",
+    )?;
+    range_list.lock().expect("lock").clear();
+    let hash = download(format!("{url_root}plink_sim_10s_100v_10pmiss.bed"), &path)?;
+    assert_eq!(
+        hash,
+        "8364a0ba9290b1ea86b609dc42e3611458aeda7bea16cc83c7752fd3698bb2ba"
+    );
+    assert_eq!(*range_list.lock().expect("lock"), [None, Some(half)]);
+    Ok(())
+}
+
+#[test]
+fn resume_only_the_same_remote_file() -> Result<(), anyhow::Error> {
+    let new_contents = b"file, version 2\n";
+    let new_hash = "sha256:".to_string()
+        + &{
+            let source_dir = TempDir::default();
+            std::fs::write(source_dir.join("file"), new_contents)?;
+            hash_file(source_dir.join("file"))?
+        };
+    // Leave a partial file from an older version of the remote file, `"v1"`.
+    let leave_partial = |temp_dir: &TempDir, partial: &[u8]| -> std::io::Result<()> {
+        std::fs::write(temp_dir.join(".file.part"), partial)?;
+        std::fs::write(temp_dir.join(".file.part.validator"), "\"v1\"")
+    };
+
+    for honors_if_range in [true, false] {
+        let (url_root, range_list) = serve_versioned(new_contents, honors_if_range);
+        let temp_dir = TempDir::default();
+        leave_partial(&temp_dir, b"OLD V1 ")?;
+        let path = temp_dir.join("file");
+        let options = FetchOptions::new().mismatch_policy(MismatchPolicy::Redownload);
+        fetch_with_options(format!("{url_root}file"), &new_hash, &path, &options)?;
+        assert_eq!(std::fs::read(&path)?, new_contents);
+        assert_eq!(dir_to_file_list(&temp_dir)?, ["file"]);
+        // If the server ignores `If-Range`, the spliced file fails its hash and is fetched again.
+        let expected: &[Option<usize>] = if honors_if_range {
+            &[Some(7)]
+        } else {
+            &[Some(7), None]
+        };
+        assert_eq!(*range_list.lock().expect("lock"), expected);
+    }
+
+    // A partial file longer than the remote file isn't mistaken for a complete one ...
+    let (url_root, range_list) = serve_versioned(new_contents, false);
+    let temp_dir = TempDir::default();
+    leave_partial(&temp_dir, b"OLD VERSION 1 IS LONGER THAN VERSION 2\n")?;
+    let path = temp_dir.join("file");
+    fetch(format!("{url_root}file"), &new_hash, &path)?;
+    assert_eq!(std::fs::read(&path)?, new_contents);
+    assert_eq!(*range_list.lock().expect("lock"), [Some(39), None]);
+
+    // ... but one just as long is used as is.
+    std::fs::remove_file(&path)?;
+    range_list.lock().expect("lock").clear();
+    leave_partial(&temp_dir, new_contents)?;
+    fetch(format!("{url_root}file"), &new_hash, &path)?;
+    assert_eq!(std::fs::read(&path)?, new_contents);
+    assert_eq!(*range_list.lock().expect("lock"), [Some(16)]);
+    Ok(())
+}
+
+#[test]
+fn partial_file_ignored_when_range_not_supported() -> Result<(), anyhow::Error> {
+    // The test server ignores `Range` headers and always sends the whole file.
+    let url_root = serve_test_data();
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("small.fam");
+    std::fs::write(
+        temp_dir.join(".small.fam.part"),
+        "not a prefix of small.fam",
+    )?;
+    fetch(
+        format!("{url_root}small.fam"),
+        "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2",
+        &path,
+    )?;
+    assert_eq!(dir_to_file_list(&temp_dir)?, ["small.fam"]);
+    Ok(())
+}
+
+#[test]
+fn retry_transient_failures() -> Result<(), anyhow::Error> {
+    let bytes = std::fs::read("tests/data/small.fam")?;
+    let request_count = Arc::new(AtomicUsize::new(0));
+    let url_root = serve_raw({
+        let request_count = Arc::clone(&request_count);
+        move |request_head, mut stream| {
+            let count = request_count.fetch_add(1, Ordering::SeqCst);
+            if request_head.starts_with("GET /missing") {
+                write_response(&mut stream, "404 Not Found", b"", |head| head);
+            } else if count % 3 == 2 {
+                write_response(&mut stream, "200 OK", &bytes, |head| head);
+            } else if count % 3 == 1 {
+                write_response(&mut stream, "429 Too Many Requests", b"", |head| {
+                    head + "Retry-After: 0\r\n"
+                });
+            } else {
+                write_response(&mut stream, "503 Service Unavailable", b"", |head| head);
+            }
+        }
+    });
+    let temp_dir = TempDir::default();
+    let path = temp_dir.join("small.fam");
+    let retry_policy = RetryPolicy::new().base_delay(Duration::from_millis(1));
+
+    // Fails twice, then succeeds.
+    download_with_options(
+        format!("{url_root}small.fam"),
+        &path,
+        &FetchOptions::new().retry_policy(retry_policy.clone()),
+    )?;
+    assert!(path.exists());
+    assert_eq!(request_count.swap(0, Ordering::SeqCst), 3);
+
+    // Gives up after `max_attempts`.
+    let result = download_with_options(
+        format!("{url_root}small.fam"),
+        temp_dir.join("other.fam"),
+        &FetchOptions::new().retry_policy(retry_policy.clone().max_attempts(2)),
+    );
+    assert!(matches!(
+        result.map_err(|e| *e),
+        Err(FetchDataError::UreqError(ureq::Error::Status(429, _)))
+    ));
+    assert_eq!(request_count.swap(0, Ordering::SeqCst), 2);
+
+    // Doesn't retry an error that isn't transient.
+    let result = download_with_options(
+        format!("{url_root}missing"),
+        temp_dir.join("missing"),
+        &FetchOptions::new().retry_policy(retry_policy),
+    );
+    assert!(result.is_err());
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn builder() -> Result<(), Box<FetchDataError>> {
+    // An explicit cache directory is used even when the environment variable is set.
+    // (Cargo sets `CARGO_MANIFEST_DIR` for tests, so the test needn't change the environment.)
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .env_key("CARGO_MANIFEST_DIR")
+        .cache_dir(&temp_dir)
+        .timeout(Duration::from_secs(10))
+        .build();
+    assert_eq!(fetch_data.cache_dir()?, *temp_dir);
+    assert_eq!(
+        fetch_data.fetch_file("small.fam")?,
+        temp_dir.join("small.fam")
+    );
+
+    // Without an explicit cache directory, the environment variable is used.
+    let fetch_data = FetchData::builder().env_key("CARGO_MANIFEST_DIR").build();
+    assert_eq!(
+        fetch_data.cache_dir()?,
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    );
+
+    // With none of those, nor an application name, there is no cache directory, rather than the
+    // user's shared cache directory.
+    let fetch_data = FetchData::builder()
+        .registry_contents("")
+        .url_root(serve_test_data())
+        .build();
+    let error = fetch_data.cache_dir().unwrap_err().to_string();
+    assert!(error.contains("Cannot create cache directory"), "{error}");
+
+    // A bad proxy is reported when a file is fetched.
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .proxy("not a proxy://")
+        .build();
+    assert!(fetch_data.fetch_file("small.bed").is_err());
+    Ok(())
+}
+
+#[test]
+fn mirror_failover() -> Result<(), anyhow::Error> {
+    let missing =
+        serve_raw(|_, mut stream| write_response(&mut stream, "404 Not Found", b"", |h| h));
+    let wrong = serve_raw(|_, mut stream| write_response(&mut stream, "200 OK", b"wrong", |h| h));
+
+    // The first mirror that has the file is used.
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_roots([missing.as_str(), wrong.as_str(), &serve_test_data()])
+        .cache_dir(&temp_dir)
+        .retry_policy(RetryPolicy::none())
+        .build();
+    let local_path = fetch_data.fetch_file("small.fam")?;
+    assert_eq!(local_path, temp_dir.join("small.fam"));
+    assert_eq!(std::fs::read_dir(&*temp_dir)?.count(), 1);
+
+    // When every mirror fails, the error tells why each one did.
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_roots([&missing, &wrong])
+        .cache_dir(&temp_dir)
+        .retry_policy(RetryPolicy::none())
+        .build();
+    let error = fetch_data.fetch_file("small.bed").unwrap_err().to_string();
+    assert!(error.contains(&format!("'{missing}small.bed'")), "{error}");
+    assert!(error.contains("404"), "{error}");
+    assert!(error.contains(&format!("'{wrong}small.bed'")), "{error}");
+    assert!(error.contains("hash"), "{error}");
+    assert!(!temp_dir.join("small.bed").exists());
+
+    // Forgetting the URL root is reported clearly, not as a list of no failures.
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .cache_dir(&temp_dir)
+        .build();
+    let error = fetch_data.fetch_file("small.bed").unwrap_err().to_string();
+    assert!(error.contains("no URL root"), "{error}");
+    let error = FetchData::builder()
+        .cache_dir(&temp_dir)
+        .build()
+        .gen_registry_contents(["small.bed"])
+        .unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::NoUrlRoot(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn per_file_url() -> Result<(), Box<FetchDataError>> {
+    // The file comes from its own URL, but is cached under its registry name.
+    let temp_dir = TempDir::default();
+    let url = format!("{}small.fam", serve_test_data());
+    let fetch_data = FetchData::builder()
+        .registry_contents(format!(
+            "reference.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2 {url}"
+        ))
+        .url_root("http://127.0.0.1:1/unused/")
+        .cache_dir(&temp_dir)
+        .build();
+    let local_path = fetch_data.fetch_file("reference.fam")?;
+    assert_eq!(local_path, temp_dir.join("reference.fam"));
+    assert_eq!(
+        fetch_data.gen_registry_contents(["reference.fam"])?,
+        format!(
+            "reference.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2 {url}\n"
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn paths_outside_cache_dir() {
+    let temp_dir = TempDir::default();
+    let hash = "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2";
+    for path in [
+        "../../.bashrc",
+        "data/../../escape",
+        "/etc/passwd",
+        "..\\escape",
+        "C:\\Windows\\win.ini",
+        "c:escape",
+    ] {
+        // In the registry.
+        let fetch_data = FetchData::builder()
+            .registry_contents(format!("{path} {hash}"))
+            .url_root(serve_test_data())
+            .cache_dir(&temp_dir)
+            .build();
+        let error = fetch_data.fetch_file("small.fam").unwrap_err().to_string();
+        assert!(
+            error.contains("leaves the cache directory"),
+            "{path}: {error}"
+        );
+
+        // As an argument.
+        let fetch_data = FetchData::builder()
+            .registry_contents(include_str!("../registry.txt"))
+            .url_root(serve_test_data())
+            .cache_dir(&temp_dir)
+            .build();
+        let error = fetch_data.fetch_file(path).unwrap_err();
+        assert!(
+            matches!(
+                *error,
+                FetchDataError::FetchDataError(FetchDataSpecificError::PathOutsideCacheDir(_))
+            ),
+            "{path}: {error}"
+        );
+        assert!(fetch_data.gen_registry_contents([path]).is_err());
+    }
+    assert_eq!(std::fs::read_dir(&*temp_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn nested_registry_paths() -> Result<(), Box<FetchDataError>> {
+    // Serve a directory with subdirectories.
+    let source_dir = TempDir::default();
+    for (path, contents) in [
+        ("chr1/a.txt", "a"),
+        ("chr2/deep/b.txt", "b"),
+        ("c.txt", "c"),
+    ] {
+        let path = source_dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    let (url_root, _) = serve_dir_counted(source_dir.to_path_buf());
+
+    let file_list = dir_to_file_list_recursive(&source_dir)?;
+    assert_eq!(file_list, ["c.txt", "chr1/a.txt", "chr2/deep/b.txt"]);
+
+    // Generate a registry with nested paths, then fetch them into a new cache directory.
+    let gen_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .url_root(&url_root)
+        .cache_dir(&gen_dir)
+        .build();
+    let registry_contents = fetch_data.gen_registry_contents(&file_list)?;
+    assert!(registry_contents.contains("chr2/deep/b.txt "));
+    assert_eq!(
+        gen_registry_contents_from_dir(&source_dir, &file_list)?,
+        registry_contents
+    );
+
+    let cache_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(registry_contents)
+        .url_root(&url_root)
+        .cache_dir(&cache_dir)
+        .build();
+    let local_list = fetch_data.fetch_files(&file_list)?;
+    assert_eq!(local_list[2], cache_dir.join("chr2/deep/b.txt"));
+    assert_eq!(std::fs::read_to_string(&local_list[2]).unwrap(), "b");
+    Ok(())
+}
+
+#[test]
+fn file_list_filters() -> Result<(), Box<FetchDataError>> {
+    let dir = TempDir::default();
+    for path in [
+        "a.bed",
+        "a.tmp",
+        ".DS_Store",
+        "chr1/b.bed",
+        "chr1/.DS_Store",
+        "chr1/deep/c.bed",
+        "scratch/d.bed",
+    ] {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    let options = FileListOptions::new()
+        .exclude("*.tmp")
+        .exclude(".DS_Store")
+        .exclude("scratch");
+    assert_eq!(
+        dir_to_file_list_with_options(&dir, &options)?,
+        ["a.bed", "chr1/b.bed", "chr1/deep/c.bed"]
+    );
+
+    // `*` doesn't cross directories, but `**` does.
+    let options = FileListOptions::new().include("chr1/*.bed");
+    assert_eq!(
+        dir_to_file_list_with_options(&dir, &options)?,
+        ["chr1/b.bed"]
+    );
+    let options = FileListOptions::new().include("chr1/**/*.bed");
+    assert_eq!(
+        dir_to_file_list_with_options(&dir, &options)?,
+        ["chr1/b.bed", "chr1/deep/c.bed"]
+    );
+
+    let options = FileListOptions::new().include("[");
+    let error = dir_to_file_list_with_options(&dir, &options).unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::BadGlob(..))
+    ));
+    Ok(())
+}
+
+#[test]
+fn registry_diff() -> Result<(), Box<FetchDataError>> {
+    // Formatting, comments, per-file URLs, and hash prefixes are kept. Paths and hashes are
+    // compared by meaning, so `./small.fam` with a `sha256:` prefix is unchanged. Added files go at the end.
+    let old_contents = "\
+# Reference data
+./small.fam   sha256:36E0086C0353FF336D0533330DBACB12C75E37DC3CBA174313635B98DFE86ED2
+small.bim\tmd5:0000 https://example.com/small.bim   # moved
+
+gone.bed 1234";
+    let new_contents =
+        gen_registry_contents_from_dir("tests/data", ["small.bim", "small.fam", "small.bed"])?;
+    let diff = diff_registry_contents(old_contents, &new_contents)?;
+    let small_bed_hash = hash_file("tests/data/small.bed")?;
+    assert_eq!(
+        diff.contents(),
+        format!(
+            "\
+# Reference data
+./small.fam   sha256:36E0086C0353FF336D0533330DBACB12C75E37DC3CBA174313635B98DFE86ED2
+small.bim\tsha256:56b6657a3766e2e52273f89d28be6135f9424ca1d204d29f3fa1c5a90eca794e https://example.com/small.bim   # moved
+
+small.bed {small_bed_hash}
+"
+        )
+    );
+    assert_eq!(diff.removed, [("gone.bed".to_string(), "1234".to_string())]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.added.len(), 1);
+
+    // Updating again changes nothing.
+    let again = diff_registry_contents(diff.contents(), &new_contents)?;
+    assert!(again.is_empty());
+    assert_eq!(again.contents(), diff.contents());
+
+    assert!(diff_registry_contents("", "no_hash").is_err());
+    assert!(diff_registry_contents("", "a.bed not_hex").is_err());
+    assert!(diff_registry_contents("", "../a.bed 1234").is_err());
+    Ok(())
+}
+
+#[test]
+fn registry_comments() -> Result<(), Box<FetchDataError>> {
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(
+            "\
+# From the PLINK test suite.
+
+small.fam 36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2  # trailing comment
+   # indented comment
+\t
+small.bim 56b6657a3766e2e52273f89d28be6135f9424ca1d204d29f3fa1c5a90eca794e https://example.com/#anchor
+",
+        )
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    // The registry parses. (A `#` inside a field, like the URL fragment, isn't a comment.)
+    fetch_data.fetch_file("small.fam")?;
+    assert!(fetch_data.fetch_file("#").is_err());
+    Ok(())
+}
+
+#[test]
+fn registry_line_problems() {
+    let hash = "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2";
+    for (registry_contents, line_number, reason) in [
+        ("# header\n\nsmall.fam".to_string(), 3, "missing hash"),
+        (
+            format!("small.fam {hash} https://example.com/ extra"),
+            1,
+            "extra column",
+        ),
+        (
+            format!("small.fam {}", hash.replace('e', "g")),
+            1,
+            "not hexadecimal",
+        ),
+        (
+            "small.fam 36e0".to_string(),
+            1,
+            "sha256 hash has 4 hex digits instead of 64",
+        ),
+        (
+            "small.fam md5:36e0".to_string(),
+            1,
+            "md5 hash has 4 hex digits",
+        ),
+        (
+            "small.fam crc:36e0".to_string(),
+            1,
+            "Unknown hash algorithm 'crc'",
+        ),
+        (
+            format!(
+                "small.fam {hash}\nsmall.fam {hash}\nsmall.fam {}",
+                hash.to_uppercase()
+            ),
+            0,
+            "",
+        ),
+        (
+            format!(
+                "small.fam sha256:{hash}\n./small.fam {}",
+                hash.to_uppercase()
+            ),
+            0,
+            "",
+        ),
+        (
+            format!(
+                "small.fam {hash}\nsmall.bim {hash}\n./small.fam {}",
+                hash.replace('3', "4")
+            ),
+            3,
+            "duplicate of line 1 with a different hash",
+        ),
+        (
+            format!("small.fam {hash}\nsmall.zip.Unpacked/small.fam {hash}"),
+            2,
+            "reserved for processor outputs",
+        ),
+        (
+            format!(".processed/small.fam {hash}"),
+            1,
+            "reserved for processor outputs",
+        ),
+    ] {
+        // The compile-time check agrees with the run-time check, except that it doesn't look
+        // for duplicates.
+        assert_eq!(
+            fetch_data::registry_problem(&registry_contents).is_some(),
+            line_number != 0 && !reason.starts_with("duplicate"),
+            "{registry_contents}"
+        );
+
+        let temp_dir = TempDir::default();
+        let fetch_data = FetchData::builder()
+            .registry_contents(&registry_contents)
+            .url_root("http://127.0.0.1:1/unused/")
+            .cache_dir(&temp_dir)
+            .build();
+        let Err(error) = fetch_data.cache_dir() else {
+            assert_eq!(line_number, 0, "{registry_contents}");
+            continue;
+        };
+        let last_line = registry_contents.lines().last().unwrap();
+        let error = error.to_string();
+        assert!(
+            error.contains(&format!("Registry line {line_number} is invalid")),
+            "{error}"
+        );
+        assert!(error.contains(reason), "{error}");
+        assert!(error.contains(&format!("'{last_line}'")), "{error}");
+    }
+
+    // A bad hash given to `fetch` is caught before downloading.
+    let temp_dir = TempDir::default();
+    let error = fetch(
+        "http://127.0.0.1:1/small.fam",
+        "md5:36e0",
+        temp_dir.join("small.fam"),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::InvalidHash(..))
+    ));
+}
+
+#[test]
+fn include_large_registry() -> Result<(), anyhow::Error> {
+    // Checking a registry of a few hundred lines at compile time stays within the const
+    // evaluation limits.
+    const REGISTRY: &str = fetch_data::include_registry!("fixtures/large_registry.txt");
+    assert_eq!(REGISTRY.lines().count(), 300);
+
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(REGISTRY)
+        .url_root("http://127.0.0.1:1/unused/")
+        .cache_dir(&temp_dir)
+        .build();
+    fetch_data.cache_dir()?;
+    Ok(())
+}
+
+#[cfg(feature = "unpack")]
+#[test]
+fn fetch_unpacked() -> Result<(), anyhow::Error> {
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    for archive in ["small.tar.gz", "small.tar.zst", "small.zip"] {
+        let dir_list = fetch_data.fetch_unpacked(archive, &Unpack::new())?;
+        assert_eq!(dir_list, [temp_dir.join(format!("{archive}.unpacked"))]);
+        assert_eq!(
+            dir_to_file_list_recursive(&dir_list[0])?,
+            ["small.bed", "small.bim", "small.fam"]
+        );
+        let member_list =
+            fetch_data.fetch_unpacked(archive, &Unpack::new().members(["small.fam"]))?;
+        assert_eq!(
+            hash_file(&member_list[0])?,
+            hash_file("tests/data/small.fam")?
+        );
+    }
+
+    // The archive is extracted only once, whichever members are asked for.
+    let member = temp_dir.join("small.zip.unpacked/small.bim");
+    std::fs::write(&member, "changed")?;
+    fetch_data.fetch_unpacked("small.zip", &Unpack::new())?;
+    let member_list = fetch_data.fetch_unpacked(
+        "small.zip",
+        &Unpack::new().members(["small.bim", "small.bed"]),
+    )?;
+    assert_eq!(member_list[0], member);
+    assert_eq!(std::fs::read_to_string(&member)?, "changed");
+
+    // ... unless its registry hash changes.
+    let url = format!("{}small.tar.zst", serve_test_data());
+    let zst_hash = hash_file("tests/data/small.tar.zst")?;
+    let fetch_data = FetchData::builder()
+        .registry_contents(format!("small.zip {zst_hash} {url}"))
+        .cache_dir(&temp_dir)
+        .mismatch_policy(MismatchPolicy::Redownload)
+        .build();
+    fetch_data.fetch_unpacked("small.zip", &Unpack::new().format(ArchiveFormat::TarZst))?;
+    assert_eq!(hash_file(&member)?, hash_file("tests/data/small.bim")?);
+
+    // Concurrent calls for different members share one extraction, rather than clobbering it.
+    let fresh_dir = TempDir::default();
+    let fresh_fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&fresh_dir)
+        .build();
+    fresh_fetch_data.fetch_file("small.tar.gz")?;
+    std::thread::scope(|scope| {
+        for member in ["small.bed", "small.bim", "small.fam"] {
+            let fetch_data = &fresh_fetch_data;
+            scope.spawn(move || {
+                let member_list = fetch_data
+                    .fetch_unpacked("small.tar.gz", &Unpack::new().members([member]))
+                    .unwrap();
+                assert_eq!(
+                    hash_file(&member_list[0]).unwrap(),
+                    hash_file(format!("tests/data/{member}")).unwrap()
+                );
+            });
+        }
+    });
+
+    // Unknown members and formats are errors.
+    assert!(fetch_data
+        .fetch_unpacked("small.zip", &Unpack::new().members(["nope"]))
+        .is_err());
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let error = fetch_data
+        .fetch_unpacked("small.fam", &Unpack::new())
+        .unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::UnknownArchiveFormat(_))
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "unpack")]
+#[test]
+fn fetch_unpacked_rejects_zip_slip() -> Result<(), anyhow::Error> {
+    // Build a zip with a member that climbs out of the extraction directory.
+    let source_dir = TempDir::default();
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(source_dir.join("evil.zip"))?);
+    writer.start_file("../evil.txt", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"evil")?;
+    writer.finish()?;
+    let (url_root, _) = serve_dir_counted(source_dir.to_path_buf());
+
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(gen_registry_contents_from_dir(&source_dir, ["evil.zip"])?)
+        .url_root(url_root)
+        .cache_dir(&temp_dir)
+        .build();
+    let error = fetch_data
+        .fetch_unpacked("evil.zip", &Unpack::new())
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("outside the directory"),
+        "{error}"
+    );
+    assert!(!temp_dir.join("evil.txt").exists());
+    assert!(!temp_dir.join("evil.zip.unpacked").exists());
+    Ok(())
+}
+
+#[cfg(feature = "decompress")]
+#[test]
+fn fetch_decompressed() -> Result<(), anyhow::Error> {
+    let small_bed = std::fs::read("tests/data/small.bed")?;
+    for compressed in [
+        "small.bed.gz",
+        "small.bed.bz2",
+        "small.bed.xz",
+        "small.bed.zst",
+    ] {
+        let temp_dir = TempDir::default();
+        let fetch_data = FetchData::builder()
+            .registry_contents(include_str!("../registry.txt"))
+            .url_root(serve_test_data())
+            .cache_dir(&temp_dir)
+            .build();
+        let local_path = fetch_data.fetch_decompressed(compressed, &Decompress::new())?;
+        assert_eq!(
+            local_path,
+            temp_dir.join(format!("{compressed}.decompressed/small.bed")),
+            "{compressed}"
+        );
+        assert_eq!(std::fs::read(&local_path)?, small_bed, "{compressed}");
+    }
+
+    // The file is decompressed only once ...
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let local_list = fetch_data.fetch_files_decompressed(["small.bed.gz"], &Decompress::new())?;
+    std::fs::write(&local_list[0], "changed")?;
+    fetch_data.fetch_decompressed("small.bed.gz", &Decompress::new())?;
+    assert_eq!(std::fs::read_to_string(&local_list[0])?, "changed");
+
+    // ... unless the compressed file changes.
+    let source_dir = TempDir::default();
+    let mut encoder = flate2::write::GzEncoder::new(
+        std::fs::File::create(source_dir.join("small.bed.gz"))?,
+        flate2::Compression::default(),
+    );
+    encoder.write_all(b"new contents")?;
+    encoder.finish()?;
+    let (url_root, _) = serve_dir_counted(source_dir.to_path_buf());
+    let fetch_data = FetchData::builder()
+        .registry_contents(gen_registry_contents_from_dir(
+            &source_dir,
+            ["small.bed.gz"],
+        )?)
+        .url_root(url_root)
+        .cache_dir(&temp_dir)
+        .mismatch_policy(MismatchPolicy::Redownload)
+        .build();
+    fetch_data.fetch_decompressed("small.bed.gz", &Decompress::new())?;
+    assert_eq!(std::fs::read_to_string(&local_list[0])?, "new contents");
+
+    // A file with no known suffix needs an explicit format.
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let error = fetch_data
+        .fetch_decompressed("small.fam", &Decompress::new())
+        .unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::UnknownCompressionFormat(_))
+    ));
+    assert!(fetch_data
+        .fetch_decompressed(
+            "small.fam",
+            &Decompress::new().format(CompressionFormat::Xz)
+        )
+        .is_err());
+    assert!(!temp_dir.join("small.fam.decompressed").exists());
+
+    // The output doesn't touch a registry file of the same name, or its partial download.
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let partial_path = temp_dir.join(".small.bed.part");
+    std::fs::write(&partial_path, "partial")?;
+    fetch_data.fetch_decompressed("small.bed.gz", &Decompress::new())?;
+    assert_eq!(std::fs::read_to_string(&partial_path)?, "partial");
+    std::fs::remove_file(&partial_path)?;
+    let local_path = fetch_data.fetch_file("small.bed")?;
+    std::fs::write(&local_path, "changed")?;
+    fetch_data.fetch_decompressed("small.bed.zst", &Decompress::new())?;
+    assert_eq!(std::fs::read_to_string(&local_path)?, "changed");
+
+    // A registry that lists a path where outputs go is rejected when loaded, before anything
+    // is written.
+    let small_bed_hash = hash_file("tests/data/small.bed")?;
+    let fetch_data = FetchData::builder()
+        .registry_contents(format!(
+            "{}\nsmall.bed.xz.decompressed/small.bed {small_bed_hash} {}small.bed",
+            include_str!("../registry.txt"),
+            serve_test_data()
+        ))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let error = fetch_data
+        .fetch_decompressed("small.bed.xz", &Decompress::new())
+        .unwrap_err();
+    assert!(error.to_string().contains("reserved"), "{error}");
+    assert!(!temp_dir.join("small.bed.xz").exists());
+    assert!(!temp_dir.join("small.bed.xz.decompressed").exists());
+    Ok(())
+}
+
+// Writes the line count of a file to a sibling `<name>.count` file, or to one in `output_dir`,
+// counting its own runs.
+struct CountLines {
+    runs: AtomicUsize,
+    output_dir: Option<PathBuf>,
+}
+
+impl Processor for CountLines {
+    fn id(&self) -> String {
+        format!("count-lines {:?}", self.output_dir)
+    }
+
+    fn process(
+        &self,
+        input: &std::path::Path,
+        cache_dir: &std::path::Path,
+    ) -> Result<Vec<PathBuf>, Box<FetchDataError>> {
+        assert!(input.starts_with(cache_dir));
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        let contents = std::fs::read_to_string(input).map_err(|e| Box::new(e.into()))?;
+        let mut output = input.as_os_str().to_owned();
+        output.push(".count");
+        let mut output = PathBuf::from(output);
+        if let Some(output_dir) = &self.output_dir {
+            output = output_dir.join(output.file_name().unwrap_or_default());
+        }
+        std::fs::write(&output, contents.lines().count().to_string())
+            .map_err(|e| Box::new(e.into()))?;
+        Ok(vec![output])
+    }
+}
+
+#[test]
+fn fetch_file_with_processor() -> Result<(), anyhow::Error> {
+    let temp_dir = TempDir::default();
+    let count_lines = CountLines {
+        runs: AtomicUsize::new(0),
+        output_dir: None,
+    };
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    let output_lists = fetch_data.fetch_files_with(["small.fam", "small.bim"], &count_lines)?;
+    assert_eq!(output_lists[0], [temp_dir.join("small.fam.count")]);
+    assert_eq!(std::fs::read_to_string(&output_lists[0][0])?, "3");
+    assert_eq!(count_lines.runs.load(Ordering::SeqCst), 2);
+
+    // Outputs outside the cache directory are remembered, too.
+    let output_dir = TempDir::default();
+    let count_lines_elsewhere = CountLines {
+        runs: AtomicUsize::new(0),
+        output_dir: Some(output_dir.to_path_buf()),
+    };
+    for _ in 0..2 {
+        assert_eq!(
+            fetch_data.fetch_file_with("small.fam", &count_lines_elsewhere)?,
+            [output_dir.join("small.fam.count")]
+        );
+    }
+    assert_eq!(count_lines_elsewhere.runs.load(Ordering::SeqCst), 1);
+
+    // The result is remembered, even by a new FetchData ...
+    let fetch_data = FetchData::builder()
+        .registry_contents(include_str!("../registry.txt"))
+        .url_root(serve_test_data())
+        .cache_dir(&temp_dir)
+        .build();
+    assert_eq!(
+        fetch_data.fetch_file_with("small.fam", &count_lines)?,
+        output_lists[0]
+    );
+    assert_eq!(count_lines.runs.load(Ordering::SeqCst), 2);
+
+    // ... but not if the output is gone ...
+    std::fs::remove_file(&output_lists[0][0])?;
+    fetch_data.fetch_file_with("small.fam", &count_lines)?;
+    assert_eq!(count_lines.runs.load(Ordering::SeqCst), 3);
+
+    // ... or the input file changes.
+    let source_dir = TempDir::default();
+    std::fs::write(source_dir.join("small.fam"), "one line")?;
+    let (url_root, _) = serve_dir_counted(source_dir.to_path_buf());
+    let fetch_data = FetchData::builder()
+        .registry_contents(gen_registry_contents_from_dir(&source_dir, ["small.fam"])?)
+        .url_root(url_root)
+        .cache_dir(&temp_dir)
+        .mismatch_policy(MismatchPolicy::Redownload)
+        .build();
+    let output_list = fetch_data.fetch_file_with("small.fam", &count_lines)?;
+    assert_eq!(std::fs::read_to_string(&output_list[0])?, "1");
+    assert_eq!(count_lines.runs.load(Ordering::SeqCst), 4);
+
+    // Built-in processors work here too.
+    #[cfg(feature = "decompress")]
+    {
+        let fetch_data = FetchData::builder()
+            .registry_contents(include_str!("../registry.txt"))
+            .url_root(serve_test_data())
+            .cache_dir(&temp_dir)
+            .build();
+        let output_list = fetch_data.fetch_file_with("small.bed.zst", &Decompress::new())?;
+        assert_eq!(
+            output_list,
+            [temp_dir.join("small.bed.zst.decompressed/small.bed")]
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn offline_mode() -> Result<(), anyhow::Error> {
+    let temp_dir = TempDir::default();
+    let (url_root, request_count) = serve_test_data_counted();
+    let builder = || {
+        FetchData::builder()
+            .registry_contents(include_str!("../registry.txt"))
+            .url_root(&url_root)
+            .cache_dir(&temp_dir)
+            .mismatch_policy(MismatchPolicy::Redownload)
+    };
+
+    // A miss fails at once, naming the file, without touching the network.
+    let error = builder()
+        .offline(true)
+        .build()
+        .fetch_file("small.fam")
+        .unwrap_err();
+    let FetchDataError::FetchDataError(FetchDataSpecificError::NotInCacheOffline(path)) = &*error
+    else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(PathBuf::from(path), temp_dir.join("small.fam"));
+    assert!(error.to_string().contains("run once with network access"));
+    assert_eq!(request_count.load(Ordering::SeqCst), 0);
+
+    // A hit is checked and returned.
+    let local_path = builder().offline(false).build().fetch_file("small.fam")?;
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        builder().offline(true).build().fetch_file("small.fam")?,
+        local_path
+    );
+
+    // A bad hit isn't downloaded again.
+    std::fs::write(&local_path, "corrupted")?;
+    let error = builder()
+        .offline(true)
+        .build()
+        .fetch_file("small.fam")
+        .unwrap_err();
+    assert!(matches!(
+        *error,
+        FetchDataError::FetchDataError(FetchDataSpecificError::DownloadedFileWrongHash(..))
+    ));
+
+    // The free functions stay offline, too.
+    let options = FetchOptions::new().offline(true);
+    let path = temp_dir.join("other.fam");
+    assert!(download_with_options(format!("{url_root}small.fam"), &path, &options).is_err());
+    assert!(fetch_with_options(
+        format!("{url_root}small.fam"),
+        "36e0086c0353ff336d0533330dbacb12c75e37dc3cba174313635b98dfe86ed2",
+        &path,
+        &options,
+    )
+    .is_err());
+    assert!(!path.exists());
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn fetch_all_report() -> Result<(), anyhow::Error> {
+    let source_dir = TempDir::default();
+    std::fs::create_dir(source_dir.join("chr1"))?;
+    std::fs::write(source_dir.join("a.txt"), "aaaa")?;
+    std::fs::write(source_dir.join("chr1/b.txt"), "bbbbbbbb")?;
+    std::fs::write(source_dir.join("c.dat"), "cc")?;
+    let mut registry_contents =
+        gen_registry_contents_from_dir(&source_dir, ["a.txt", "chr1/b.txt", "c.dat"])?;
+    registry_contents.push_str(&format!("gone.txt {}\n", "0".repeat(64)));
+    let (url_root, _) = serve_dir_counted(source_dir.to_path_buf());
+    let temp_dir = TempDir::default();
+    let fetch_data = FetchData::builder()
+        .registry_contents(registry_contents)
+        .url_root(url_root)
+        .cache_dir(&temp_dir)
+        .build();
+
+    // Files are matched at any depth and reported by path.
+    let report = fetch_data.fetch_matching("*.txt")?;
+    let path_list: Vec<&str> = report.files.iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(path_list, ["a.txt", "chr1/b.txt", "gone.txt"]);
+    assert!(matches!(report.files[0].status, FetchStatus::Downloaded));
+    assert_eq!(report.files[1].local_path, temp_dir.join("chr1/b.txt"));
+    assert_eq!(report.bytes_transferred(), 12);
+    assert!(!report.is_success());
+    let failure_list: Vec<&str> = report.failures().map(|file| file.path.as_str()).collect();
+    assert_eq!(failure_list, ["gone.txt"]);
+
+    // Cached files aren't downloaded again.
+    let report = fetch_data.fetch_all()?;
+    let status_list: Vec<&str> = report
+        .files
+        .iter()
+        .map(|file| match file.status {
+            FetchStatus::AlreadyCached => "cached",
+            FetchStatus::Downloaded => "downloaded",
+            FetchStatus::Failed(_) => "failed",
+        })
+        .collect();
+    assert_eq!(status_list, ["cached", "downloaded", "cached", "failed"]);
+    assert_eq!(report.bytes_transferred(), 2);
+    assert_eq!(
+        report.to_string(),
+        "4 files: 2 already cached, 1 downloaded (2 bytes), 1 failed"
+    );
+
+    // A bad pattern is an error.
+    assert!(matches!(
+        *fetch_data.fetch_matching("[").unwrap_err(),
+        FetchDataError::FetchDataError(FetchDataSpecificError::BadGlob(..))
+    ));
+    Ok(())
+}
+
 #[test]
 fn bad_fetch_data() -> Result<(), Box<FetchDataError>> {
     // Create list of files in data directory
@@ -207,3 +1609,131 @@ static STATIC_TEST_API: FetchData = FetchData::new(
 pub fn sample_file(path: AnyPath) -> Result<PathBuf, Box<FetchDataError>> {
     STATIC_TEST_API.fetch_file(path)
 }
+
+/// Serve `tests/data` from localhost, so download tests don't need the Internet.
+fn serve_test_data() -> String {
+    serve_test_data_counted().0
+}
+
+/// Like `serve_test_data`, but also returns a count of the requests served.
+fn serve_test_data_counted() -> (String, Arc<AtomicUsize>) {
+    serve_dir_counted(PathBuf::from("tests/data"))
+}
+
+/// Serve the files in `dir` from localhost. Returns the URL root and a count of the requests served.
+fn serve_dir_counted(dir: PathBuf) -> (String, Arc<AtomicUsize>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("local test server");
+    let url_root = format!("http://{}/", server.server_addr());
+    let request_count = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&request_count);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let path = dir.join(request.url().trim_start_matches('/'));
+            let _ = match std::fs::read(path) {
+                Ok(bytes) => request.respond(tiny_http::Response::from_data(bytes)),
+                Err(_) => request.respond(tiny_http::Response::empty(404)),
+            };
+        }
+    });
+    (url_root, request_count)
+}
+
+/// Serve raw HTTP from localhost. For each connection, `handler` gets the request's head
+/// (request line and headers) and writes a response. The connection closes when the stream is dropped.
+fn serve_raw(mut handler: impl FnMut(&str, TcpStream) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("local test server");
+    let url_root = format!("http://{}/", listener.local_addr().expect("local address"));
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+            let mut request_head = String::new();
+            while reader.read_line(&mut request_head).is_ok_and(|len| len > 2) {}
+            handler(&request_head, stream);
+        }
+    });
+    url_root
+}
+
+/// Write a complete response. `add_headers` may append header lines to the head.
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    body: &[u8],
+    add_headers: impl FnOnce(String) -> String,
+) {
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    let head = add_headers(head) + "\r\n";
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(body);
+}
+
+/// Serve `contents` as every path, with the validator `"v2"`. A `Range` request past the end gets
+/// a 416 response. Otherwise, if `honors_if_range`, a `Range` request whose `If-Range` doesn't
+/// match gets the whole file. Returns the URL root and the `Range` start of each request.
+fn serve_versioned(
+    contents: &'static [u8],
+    honors_if_range: bool,
+) -> (String, Arc<Mutex<Vec<Option<usize>>>>) {
+    let range_list = Arc::new(Mutex::new(Vec::new()));
+    let url_root = serve_raw({
+        let range_list = Arc::clone(&range_list);
+        move |request_head, mut stream| {
+            let range_start = range_start(request_head);
+            range_list.lock().expect("lock").push(range_start);
+            let same_version = header(request_head, "If-Range") == Some("\"v2\"");
+            match range_start {
+                Some(start) if start >= contents.len() => {
+                    write_response(&mut stream, "416 Range Not Satisfiable", b"", |head| {
+                        format!("{head}Content-Range: bytes */{}\r\n", contents.len())
+                    });
+                }
+                Some(start) if same_version || !honors_if_range => {
+                    write_response(
+                        &mut stream,
+                        "206 Partial Content",
+                        &contents[start..],
+                        |head| {
+                            format!(
+                                "{head}ETag: \"v2\"\r\nContent-Range: bytes {start}-{}/{}\r\n",
+                                contents.len() - 1,
+                                contents.len()
+                            )
+                        },
+                    );
+                }
+                _ => write_response(&mut stream, "200 OK", contents, |head| {
+                    head + "ETag: \"v2\"\r\n"
+                }),
+            }
+        }
+    });
+    (url_root, range_list)
+}
+
+/// The value of a request header, if any.
+fn header<'a>(request_head: &'a str, name: &str) -> Option<&'a str> {
+    request_head.lines().find_map(|line| {
+        let (line_name, value) = line.split_once(':')?;
+        line_name.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// The start of a request's `Range: bytes=<start>-` header, if any.
+fn range_start(request_head: &str) -> Option<usize> {
+    request_head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.eq_ignore_ascii_case("range") {
+            return None;
+        }
+        value
+            .trim()
+            .strip_prefix("bytes=")?
+            .strip_suffix('-')?
+            .parse()
+            .ok()
+    })
+}